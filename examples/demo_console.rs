@@ -3,14 +3,31 @@
 ///
 /// `cargo run --example demo_console --features amethyst-system`
 ///
+/// Properties can be overridden at launch, eg. `... -- +set width 120 +height 80`, or with
+/// environment variables such as `DEMO_CVAR_WIDTH=120`.
+///
 /// Type `help` see available commands.
 ///
 /// Examples:
 ///  * `width 120` - Set width to 120
 ///  * `width` - Print the current width
+///  * `color "light blue"` - Quote values containing spaces
+///  * `width 120; height 80` - Run several commands, `&&` and `||` run conditionally
+///  * `history` - List previous commands, `!2` runs the second one again
+///  * `TAB` - Complete command and property names
+///  * `color <TAB>` - Complete property values and action arguments
+///  * `Ctrl+R` - Search previous commands as you type
 ///  * `reset width` - Reset width to its default value (100)
 ///  * `find a` - Find all commands with `a` in their name
 ///  * `reset` - Reset all variables to their defaults
+///  * `undo` / `redo` - Revert property changes made from the console, or press `Ctrl+Z` / `Ctrl+Y` with an empty prompt
+///  * `begin`, `width 120`, `color red`, `commit` - Stage several properties and set them together, or `rollback` to discard them
+///  * `writeconfig my.cfg` - Save all properties to a file, `exec my.cfg` loads them again
+///  * `changed` - List properties that differ from their defaults, `changed script` prints them as commands
+///  * `preset save fast` - Snapshot all properties, then `preset load fast`, `preset list`, `preset diff fast slow` or `preset delete fast`
+///  * `log.level debug` - Show more log records, `log.filter.amethyst_rendy off` mutes one crate or module
+///  * `console.scrollback 5000` - Keep more lines of output, the oldest ones are dropped first
+///  * Type in the Filter box to only show matching lines, tick Regex with the `regex` feature
 use amethyst::{
     ecs::{Read, System},
     input::{InputBundle, StringBindings},
@@ -19,7 +36,7 @@ use amethyst::{
     utils::application_root_dir,
 };
 
-use amethyst_console::{amethyst_imgui::RenderImgui, IConsoleExt, IVisitExt};
+use amethyst_console::{amethyst_imgui::RenderImgui, Completions, IConsoleExt, IVisitExt};
 
 pub struct ArenaConfig {
     pub height: f32,
@@ -75,7 +92,7 @@ impl IVisitExt for PaddleConfig {
     fn visit_mut_ext(
         &mut self,
         f: &mut dyn FnMut(&mut dyn cvar::INode),
-        _console: &mut dyn IConsoleExt,
+        console: &mut dyn IConsoleExt,
     ) {
         let default = Self::default();
         f(&mut cvar::Property(
@@ -90,6 +107,8 @@ impl IVisitExt for PaddleConfig {
             &mut self.color,
             default.color,
         ));
+        // Suggest values when completing `color <TAB>`
+        console.complete("color", 0, Completions::Values(&["white", "red", "blue"]));
     }
 }
 
@@ -154,8 +173,33 @@ impl<'s> System<'s> for ExampleSystem {
 struct Example;
 impl SimpleState for Example {}
 
+/// Prints log records to the terminal, as `amethyst::start_logger` would
+struct StdoutLog;
+
+impl log::Log for StdoutLog {
+    fn enabled(&self, _: &log::Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record) {
+        println!(
+            "[{}][{}] {}",
+            record.level(),
+            record.target(),
+            record.args()
+        );
+    }
+
+    fn flush(&self) {}
+}
+
 fn main() -> amethyst::Result<()> {
-    amethyst::start_logger(amethyst::LoggerConfig::default());
+    // Show log records in the console window, and still print them to the terminal.
+    // `amethyst::start_logger` installs its own global logger, so it can't be used as well.
+    let log = amethyst_console::LogQueue::default();
+    amethyst_console::ConsoleLogger::new(log.clone())
+        .with_next(Box::new(StdoutLog))
+        .init()?;
     let app_root = application_root_dir()?;
     let display_config_path = app_root.join("examples/display.ron");
 
@@ -164,7 +208,20 @@ fn main() -> amethyst::Result<()> {
     //    - Initialize the struct to its default value
     //    - Add it to the world so other services can read in their run loops
     //    - Create a console window with everything added by `visit_mut_ext`
-    let console_system = amethyst_console::create_system::<GameConfig>();
+    //    - Keep command history in a file between sessions
+    //    - Keep presets in a directory between sessions
+    //    - Accept commands in any case, eg. `HELP`
+    //    - Run the commands in `autoexec.cfg` on the first frame, if it exists
+    //    - Apply overrides from `DEMO_CVAR_*` environment variables
+    //    - Show log records, colored by level
+    let console_system = amethyst_console::create_system_with::<GameConfig>(
+        amethyst_console::create_console(Some(app_root.join("autoexec.cfg")))
+            .with_history_file(app_root.join("console_history"))
+            .with_preset_dir(app_root.join("presets"))
+            .with_ignore_case(true)
+            .with_log(log),
+    )
+    .with_env_prefix("DEMO_CVAR_");
 
     let game_data = GameDataBuilder::default()
         .with_barrier()
@@ -1,6 +1,6 @@
 pub use amethyst_imgui;
 
-use crate::{ConsoleWindow, IVisitExt, VisitMutExt};
+use crate::{command_line_overrides, ConsoleWindow, IVisitExt, Layer, VisitMutExt};
 use amethyst::{
     core::{
         shrev::{EventChannel, ReaderId},
@@ -12,6 +12,7 @@ use amethyst::{
 };
 use imgui::im_str;
 use std::marker::PhantomData;
+use std::path::PathBuf;
 
 /// Amethyst system to manage configuration updates, and console window rendering
 ///
@@ -20,6 +21,7 @@ use std::marker::PhantomData;
 pub struct ConsoleSystem<T> {
     open: bool,
     console: ConsoleWindow,
+    env_prefix: Option<String>,
     event_reader: Option<ReaderId<InputEvent<StringBindings>>>,
     _marker: PhantomData<T>,
 }
@@ -29,18 +31,42 @@ impl<T> ConsoleSystem<T> {
         ConsoleSystem {
             open: true,
             console,
+            env_prefix: None,
             event_reader: None,
             _marker: PhantomData,
         }
     }
+
+    /// Override properties from environment variables starting with `prefix`, eg. `GAME_CVAR_`.
+    ///
+    /// See `env_var_name` for how property paths map to variable names. These are applied before
+    /// the command line overrides, so those take precedence.
+    pub fn with_env_prefix<S: Into<String>>(mut self, prefix: S) -> Self {
+        self.env_prefix = Some(prefix.into());
+        self
+    }
 }
 
 impl<'a, 'b, T> SystemDesc<'a, 'b, ConsoleSystem<T>> for ConsoleSystem<T>
 where
     T: 'static + std::marker::Send + std::marker::Sync + std::default::Default + IVisitExt,
 {
-    fn build(self, world: &mut World) -> ConsoleSystem<T> {
+    fn build(mut self, world: &mut World) -> ConsoleSystem<T> {
         world.insert(T::default());
+        if let Some(prefix) = &self.env_prefix {
+            self.console
+                .apply_env_overrides(&mut *world.fetch_mut::<T>(), prefix);
+        }
+        self.console.apply_overrides(
+            &mut *world.fetch_mut::<T>(),
+            Layer::CommandLine,
+            "command line",
+            &command_line_overrides(
+                std::env::args_os()
+                    .skip(1)
+                    .filter_map(|arg| arg.into_string().ok()),
+            ),
+        );
         world.setup::<Read<EventChannel<InputEvent<StringBindings>>>>();
         let event_reader = world
             .fetch_mut::<EventChannel<InputEvent<StringBindings>>>()
@@ -48,6 +74,7 @@ where
         ConsoleSystem {
             open: self.open,
             console: self.console,
+            env_prefix: self.env_prefix,
             event_reader: Some(event_reader),
             _marker: PhantomData,
         }
@@ -82,6 +109,9 @@ where
         });
 
         let open = self.open;
+        if !open {
+            self.console.update(&mut root);
+        }
         amethyst_imgui::with(|ui| {
             let window = imgui::Window::new(im_str!("Console")).opened(&mut self.open);
             if open {
@@ -102,9 +132,17 @@ fn init_system<T>(mut console_window: ConsoleWindow) -> ConsoleSystem<T> {
     ConsoleSystem::new(console_window)
 }
 
-/// Creates an amethyst ConsoleSystem for the given datatype <T>.
-/// This will automatically initialize the resource to its default value.
-pub fn create_system<T>() -> ConsoleSystem<T> {
-    let console_window = crate::create_console();
+/// Creates an amethyst ConsoleSystem for the given datatype <T>, running the `autoexec` script
+/// on the first frame if given.
+/// This will automatically initialize the resource to its default value, and then apply any
+/// `+set name value` overrides from the command line, see `command_line_overrides`.
+pub fn create_system<T>(autoexec: Option<PathBuf>) -> ConsoleSystem<T> {
+    let console_window = crate::create_console(autoexec);
+    init_system(console_window)
+}
+
+/// Creates an amethyst ConsoleSystem for the given datatype <T>, using an existing console window.
+/// Use this to configure the window first, eg. `create_console(None).with_history_file(...)`.
+pub fn create_system_with<T>(console_window: ConsoleWindow) -> ConsoleSystem<T> {
     init_system(console_window)
 }
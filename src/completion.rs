@@ -0,0 +1,474 @@
+use crate::shell::split;
+use crate::{Completions, ConsoleResult, IConsoleExt, IVisitExt, NodeExt, VisitMutExt};
+use imgui::{sys, ImString};
+
+/// Most entries shown at once in the completion popup
+const POPUP_ITEMS: usize = 10;
+
+/// A path or argument value that completes the current word
+pub(crate) struct Candidate {
+    pub text: String,
+    /// Current value or argument usage, see `NodeExt::hint`
+    pub hint: String,
+}
+
+/// Result of completing the word under the cursor
+pub(crate) struct Completion {
+    /// Byte range of the word being completed
+    pub start: usize,
+    pub end: usize,
+    /// Every known path or argument value starting with the word, sorted
+    pub candidates: Vec<Candidate>,
+}
+
+impl Completion {
+    /// Text to replace the word with: the whole candidate if unique, else their shared prefix.
+    pub fn replacement(&self) -> Option<String> {
+        match self.candidates.as_slice() {
+            [] => None,
+            [only] => Some(format!("{} ", only.text)),
+            [first, rest @ ..] => {
+                let mut prefix = first.text.as_str();
+                for other in rest {
+                    let len = prefix
+                        .char_indices()
+                        .zip(other.text.chars())
+                        .take_while(|((_, a), b)| a == b)
+                        .last()
+                        .map_or(0, |((i, c), _)| i + c.len_utf8());
+                    prefix = &prefix[..len];
+                }
+                Some(prefix.to_string())
+            }
+        }
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.candidates.iter().map(|c| c.text.as_str()).collect()
+    }
+}
+
+/// Complete the word under `cursor`, up to the next separator after it.
+///
+/// Arguments use the values registered with `IConsoleExt::complete`, or the property's own
+/// `cvar::IProperty::values`. Anything else is completed against every path in `root`.
+pub(crate) fn complete(root: &mut dyn IVisitExt, line: &str, cursor: usize) -> Completion {
+    let separator = |c: char| c.is_whitespace() || c == ';' || c == '&' || c == '|';
+    let start = line[..cursor].rfind(separator).map_or(0, |i| i + 1);
+    let end = line[cursor..]
+        .find(separator)
+        .map_or(line.len(), |i| cursor + i);
+    let word = &line[start..end];
+
+    let mut candidates = vec![];
+    if let Some(values) = complete_arg(root, &line[..start], word) {
+        candidates = values;
+    } else {
+        let mut root = VisitMutExt(|f, console| root.visit_mut_ext(f, console));
+        cvar::console::walk(&mut root, |path, node| {
+            if path.starts_with(word) {
+                candidates.push(Candidate {
+                    text: path.to_string(),
+                    hint: node.hint(),
+                });
+            }
+        });
+    }
+    candidates.sort_by(|a, b| a.text.cmp(&b.text));
+    candidates.dedup_by(|a, b| a.text == b.text);
+
+    Completion {
+        start,
+        end,
+        candidates,
+    }
+}
+
+/// Values for the argument being typed after `before`.
+///
+/// Returns `None` if it does not follow a command, or that argument has nothing to offer.
+fn complete_arg(root: &mut dyn IVisitExt, before: &str, word: &str) -> Option<Vec<Candidate>> {
+    // Only the last command on the line matters
+    let command = before
+        .rfind([';', '&', '|'])
+        .map_or(before, |i| &before[i + 1..]);
+    let words = split(command).ok()?;
+    let path = words.first()?.as_str();
+
+    let arg = words.len() - 1;
+    let mut console = ArgCompletions {
+        path,
+        arg,
+        word,
+        found: vec![],
+    };
+    let mut values = vec![];
+    if arg == 0 {
+        let mut root = VisitMutExt(|f, console| root.visit_mut_ext(f, console));
+        cvar::console::find(&mut root, path, |node| {
+            if let cvar::NodeMut::Prop(prop) = node.as_node_mut() {
+                if let Some(list) = prop.values() {
+                    values.extend(list.iter().map(|v| v.to_string()));
+                }
+            }
+        });
+    }
+    root.visit_mut_ext(&mut |_| {}, &mut console);
+    values.extend(console.found);
+    if values.is_empty() {
+        return None;
+    }
+
+    Some(
+        values
+            .into_iter()
+            .filter(|value| value.starts_with(word))
+            .map(|text| Candidate {
+                text,
+                hint: String::new(),
+            })
+            .collect(),
+    )
+}
+
+/// Console passed to `IVisitExt::visit_mut_ext` while completing, collecting argument values.
+struct ArgCompletions<'a> {
+    path: &'a str,
+    arg: usize,
+    word: &'a str,
+    found: Vec<String>,
+}
+
+impl IConsoleExt for ArgCompletions<'_> {
+    fn write(&mut self, _: &str) {}
+    fn write_result(&mut self, _: ConsoleResult) {}
+    fn write_colored(&mut self, _: [f32; 4], _: &str) {}
+
+    fn complete(&mut self, path: &str, arg: usize, completions: Completions) {
+        if path != self.path || arg != self.arg {
+            return;
+        }
+        match completions {
+            Completions::Values(values) => self.found.extend(values.iter().map(|v| v.to_string())),
+            Completions::With(source) => self.found.extend(source(self.word)),
+        }
+    }
+}
+
+impl std::fmt::Write for ArgCompletions<'_> {
+    fn write_str(&mut self, _: &str) -> std::fmt::Result {
+        Ok(())
+    }
+}
+
+impl cvar::IConsole for ArgCompletions<'_> {
+    fn write_error(&mut self, _: &(dyn std::error::Error + 'static)) {}
+}
+
+/// Completions listed under the prompt while typing
+#[derive(Default)]
+pub(crate) struct CompletionPopup {
+    /// Prompt text and cursor the completion was made for
+    line: String,
+    cursor: usize,
+    completion: Option<Completion>,
+    selected: Option<usize>,
+}
+
+impl CompletionPopup {
+    /// Complete again if the prompt changed since the last update.
+    pub fn update(&mut self, root: &mut dyn IVisitExt, line: &str, cursor: usize) {
+        if self.line == line && self.cursor == cursor {
+            return;
+        }
+        self.line = line.to_string();
+        self.cursor = cursor;
+        self.selected = None;
+        self.completion = Some(complete(root, line, cursor));
+    }
+
+    /// Close until the prompt is edited again, eg. after recalling a line from history.
+    pub fn hide(&mut self, line: &str, cursor: usize) {
+        self.line = line.to_string();
+        self.cursor = cursor;
+        self.selected = None;
+        self.completion = None;
+    }
+
+    /// Only worth showing once something was typed, and while it is not already complete.
+    pub fn is_open(&self) -> bool {
+        match &self.completion {
+            Some(completion) => {
+                let word = &self.line[completion.start..completion.end];
+                match completion.candidates.as_slice() {
+                    [] => false,
+                    [only] => !word.is_empty() && only.text != word,
+                    _ => !word.is_empty(),
+                }
+            }
+            None => false,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.completion
+            .as_ref()
+            .map_or(0, |c| c.candidates.len().min(POPUP_ITEMS))
+    }
+
+    pub fn select_prev(&mut self) {
+        let len = self.len();
+        self.selected = match self.selected {
+            Some(0) | None => len.checked_sub(1),
+            Some(i) => Some(i - 1),
+        };
+    }
+
+    pub fn select_next(&mut self) {
+        let len = self.len();
+        self.selected = match self.selected {
+            Some(i) if i + 1 < len => Some(i + 1),
+            _ if len > 0 => Some(0),
+            _ => None,
+        };
+    }
+
+    /// Insert the selected entry, returning the new prompt text and cursor position.
+    pub fn accept(&mut self) -> Option<(String, usize)> {
+        if !self.is_open() {
+            return None;
+        }
+        let completion = self.completion.as_ref()?;
+        let candidate = &completion.candidates[self.selected?];
+        let text = format!("{} ", candidate.text);
+        let line = format!(
+            "{}{}{}",
+            &self.line[..completion.start],
+            text,
+            &self.line[completion.end..]
+        );
+        let cursor = completion.start + text.len();
+        self.completion = None;
+        Some((line, cursor))
+    }
+
+    /// Draw the popup under the last item, or above it if there is no room below.
+    pub fn build(&self, ui: &imgui::Ui) {
+        let completion = match &self.completion {
+            Some(completion) => completion,
+            None => return,
+        };
+
+        let [x, top] = ui.item_rect_min();
+        let bottom = ui.item_rect_max()[1];
+        let height = (self.len() + 1) as f32 * ui.text_line_height_with_spacing();
+        let (y, pivot) = if bottom + height > ui.io().display_size[1] {
+            (top, 1.)
+        } else {
+            (bottom, 0.)
+        };
+        // SAFETY: only sets a value imgui reads when the tooltip window is created below
+        unsafe {
+            sys::igSetNextWindowPos(
+                [x, y].into(),
+                imgui::Condition::Always as i32,
+                [0., pivot].into(),
+            );
+        }
+
+        ui.tooltip(|| {
+            let labels = completion
+                .candidates
+                .iter()
+                .take(POPUP_ITEMS)
+                .map(|c| ImString::new(c.text.as_str()))
+                .collect::<Vec<_>>();
+            let column = ui.cursor_pos()[0]
+                + labels
+                    .iter()
+                    .map(|label| ui.calc_text_size(label, false, -1.)[0])
+                    .fold(0., f32::max)
+                + 2. * ui.text_line_height();
+
+            for (i, (label, candidate)) in labels.iter().zip(&completion.candidates).enumerate() {
+                imgui::Selectable::new(label)
+                    .selected(self.selected == Some(i))
+                    .build(ui);
+                if !candidate.hint.is_empty() {
+                    ui.same_line(column);
+                    ui.text_disabled(&candidate.hint);
+                }
+            }
+
+            let more = completion.candidates.len().saturating_sub(POPUP_ITEMS);
+            if more > 0 {
+                ui.text_disabled(format!("... {} more", more));
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn completion(candidates: &[&str]) -> Completion {
+        Completion {
+            start: 0,
+            end: 0,
+            candidates: candidates
+                .iter()
+                .map(|text| Candidate {
+                    text: text.to_string(),
+                    hint: String::new(),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn replacement() {
+        assert_eq!(completion(&[]).replacement(), None);
+        assert_eq!(
+            completion(&["width"]).replacement().as_deref(),
+            Some("width ")
+        );
+        assert_eq!(
+            completion(&["width", "wide", "window"])
+                .replacement()
+                .as_deref(),
+            Some("wi")
+        );
+        assert_eq!(completion(&["a", "b"]).replacement().as_deref(), Some(""));
+        assert_eq!(
+            completion(&["éa", "éb"]).replacement().as_deref(),
+            Some("é")
+        );
+    }
+
+    struct Config {
+        width: i32,
+        wide: bool,
+        color: String,
+        arena: Arena,
+    }
+
+    /// A property offering its own values
+    struct Mode<'a>(&'a mut String);
+
+    impl cvar::INode for Mode<'_> {
+        fn name(&self) -> &str {
+            "mode"
+        }
+        fn description(&self) -> &str {
+            ""
+        }
+        fn as_node_mut(&mut self) -> cvar::NodeMut<'_> {
+            cvar::NodeMut::Prop(self)
+        }
+        fn as_inode_mut(&mut self) -> &mut dyn cvar::INode {
+            self
+        }
+    }
+
+    impl cvar::IProperty for Mode<'_> {
+        fn get(&self) -> String {
+            self.0.clone()
+        }
+        fn set(&mut self, val: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+            *self.0 = val.to_string();
+            Ok(())
+        }
+        fn reset(&mut self) {
+            self.0.clear();
+        }
+        fn default(&self) -> String {
+            String::new()
+        }
+        fn state(&self) -> cvar::PropState {
+            cvar::PropState::Default
+        }
+        fn values(&self) -> Option<&[&str]> {
+            Some(&["easy", "normal", "nightmare"])
+        }
+    }
+
+    struct Arena {
+        mode: String,
+    }
+
+    impl cvar::IVisit for Arena {
+        fn visit_mut(&mut self, f: &mut dyn FnMut(&mut dyn cvar::INode)) {
+            f(&mut Mode(&mut self.mode));
+        }
+    }
+
+    impl IVisitExt for Config {
+        fn visit_mut_ext(
+            &mut self,
+            f: &mut dyn FnMut(&mut dyn cvar::INode),
+            console: &mut dyn IConsoleExt,
+        ) {
+            f(&mut cvar::Property("width", "", &mut self.width, 0));
+            f(&mut cvar::Property("wide", "", &mut self.wide, false));
+            f(&mut cvar::Property(
+                "color",
+                "",
+                &mut self.color,
+                String::new(),
+            ));
+            console.complete("color", 0, Completions::Values(&["red", "green", "grey"]));
+            f(&mut cvar::List("arena", "", &mut self.arena));
+            f(&mut Mode(&mut self.arena.mode));
+        }
+    }
+
+    fn config() -> Config {
+        Config {
+            width: 0,
+            wide: false,
+            color: String::new(),
+            arena: Arena {
+                mode: String::new(),
+            },
+        }
+    }
+
+    fn names(root: &mut dyn IVisitExt, line: &str) -> Vec<String> {
+        let completion = complete(root, line, line.len());
+        completion.names().into_iter().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn complete_paths_and_values() {
+        let mut config = config();
+        assert_eq!(names(&mut config, "wi"), ["wide", "width"]);
+        assert_eq!(names(&mut config, "width 1; widt"), ["width"]);
+        assert_eq!(names(&mut config, "color g"), ["green", "grey"]);
+        assert_eq!(names(&mut config, "width 1 && color r"), ["red"]);
+        assert!(names(&mut config, "color red g").is_empty());
+    }
+
+    #[test]
+    fn complete_word_under_cursor() {
+        let mut config = config();
+        let completion = complete(&mut config, "color r; widt", 11);
+        assert_eq!((completion.start, completion.end), (9, 13));
+        assert_eq!(completion.replacement().as_deref(), Some("width "));
+        let completion = complete(&mut config, "col red", 1);
+        assert_eq!((completion.start, completion.end), (0, 3));
+        assert_eq!(completion.names(), ["color"]);
+        assert!(complete(&mut config, "wix", 2).candidates.is_empty());
+    }
+
+    #[test]
+    fn complete_property_values() {
+        let mut config = config();
+        assert_eq!(names(&mut config, "mode n"), ["nightmare", "normal"]);
+        assert_eq!(names(&mut config, "arena.mode n"), ["nightmare", "normal"]);
+        assert_eq!(
+            names(&mut config, "arena.mode "),
+            ["easy", "nightmare", "normal"]
+        );
+    }
+}
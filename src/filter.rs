@@ -0,0 +1,214 @@
+use crate::ColoredConsole;
+use imgui::ImString;
+use std::collections::VecDeque;
+
+/// Hides console output that doesn't match the text typed in the filter box.
+pub(crate) struct OutputFilter {
+    pub text: ImString,
+    /// Match `text` as a regular expression instead of a substring
+    pub regex: bool,
+    /// `text` and `regex` that `pattern` was built from
+    built: (String, bool),
+    pattern: Pattern,
+    /// Lines that matched, numbered from the first line written, see `ColoredConsole::dropped`
+    rows: VecDeque<usize>,
+    /// Lines before this one won't change, so they are only matched once
+    checked: usize,
+    /// `ColoredConsole::dropped` when `rows` were last updated
+    dropped: usize,
+}
+
+/// What lines are matched against
+enum Pattern {
+    None,
+    /// Lowercase text
+    Substring(String),
+    #[cfg(feature = "regex")]
+    Regex(regex::Regex),
+    /// `text` isn't a valid regex, so nothing is filtered
+    #[cfg(feature = "regex")]
+    Invalid(String),
+}
+
+impl Default for OutputFilter {
+    fn default() -> Self {
+        OutputFilter {
+            text: ImString::with_capacity(256),
+            regex: false,
+            built: (String::new(), false),
+            pattern: Pattern::None,
+            rows: VecDeque::new(),
+            checked: 0,
+            dropped: 0,
+        }
+    }
+}
+
+impl OutputFilter {
+    /// Match against the current `text` and `regex`, after they may have been edited.
+    pub fn update(&mut self) {
+        let current = (self.text.to_string(), self.regex);
+        if current == self.built {
+            return;
+        }
+
+        self.pattern = if self.text.is_empty() {
+            Pattern::None
+        } else if self.regex {
+            self.build_regex()
+        } else {
+            Pattern::Substring(self.text.to_str().to_lowercase())
+        };
+        self.built = current;
+        self.rows.clear();
+        self.checked = 0;
+    }
+
+    /// Match the lines written to `console` since the last call, if any lines are hidden.
+    ///
+    /// The last line is matched again each time, as more may have been written to it.
+    pub fn update_rows(&mut self, console: &ColoredConsole) {
+        if !self.is_active() {
+            return;
+        }
+        let first = console.dropped();
+        let end = first + console.line_count();
+        while self.rows.front().is_some_and(|&n| n < first) {
+            self.rows.pop_front();
+        }
+        while self.rows.back().is_some_and(|&n| n >= self.checked) {
+            self.rows.pop_back();
+        }
+        for n in self.checked.max(first)..end {
+            if self.matches(&console.line_text(n - first)) {
+                self.rows.push_back(n);
+            }
+        }
+        self.checked = end.saturating_sub(1).max(first);
+        self.dropped = first;
+    }
+
+    /// Number of lines left by the filter, once `update_rows` is called
+    pub fn row_count(&self) -> usize {
+        self.rows.len()
+    }
+
+    /// Index in the console of the `row`th line left by the filter
+    pub fn row(&self, row: usize) -> usize {
+        self.rows[row] - self.dropped
+    }
+
+    #[cfg(feature = "regex")]
+    fn build_regex(&self) -> Pattern {
+        match regex::Regex::new(self.text.to_str()) {
+            Ok(regex) => Pattern::Regex(regex),
+            Err(e) => Pattern::Invalid(e.to_string()),
+        }
+    }
+
+    #[cfg(not(feature = "regex"))]
+    fn build_regex(&self) -> Pattern {
+        Pattern::Substring(self.text.to_str().to_lowercase())
+    }
+
+    /// Whether any lines are hidden
+    pub fn is_active(&self) -> bool {
+        match self.pattern {
+            Pattern::None => false,
+            #[cfg(feature = "regex")]
+            Pattern::Invalid(_) => false,
+            _ => true,
+        }
+    }
+
+    /// Why `text` can't be used as a regex, if it can't
+    pub fn error(&self) -> Option<&str> {
+        match &self.pattern {
+            #[cfg(feature = "regex")]
+            Pattern::Invalid(e) => Some(e),
+            _ => None,
+        }
+    }
+
+    /// Whether `line` should be shown. Substrings are matched regardless of case.
+    pub fn matches(&self, line: &str) -> bool {
+        match &self.pattern {
+            Pattern::None => true,
+            Pattern::Substring(lower) => line.to_lowercase().contains(lower.as_str()),
+            #[cfg(feature = "regex")]
+            Pattern::Regex(regex) => regex.is_match(line),
+            #[cfg(feature = "regex")]
+            Pattern::Invalid(_) => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(text: &str, regex: bool) -> OutputFilter {
+        let mut filter = OutputFilter {
+            text: ImString::new(text),
+            regex,
+            ..OutputFilter::default()
+        };
+        filter.update();
+        filter
+    }
+
+    fn rows(filter: &mut OutputFilter, console: &ColoredConsole) -> Vec<String> {
+        filter.update_rows(console);
+        (0..filter.row_count())
+            .map(|row| console.line_text(filter.row(row)))
+            .collect()
+    }
+
+    #[test]
+    fn substring() {
+        let warn = filter("WARN", false);
+        assert!(warn.is_active());
+        assert!(warn.matches("[Warn][gfx] slow"));
+        assert!(!warn.matches("[INFO][gfx] fast"));
+        assert!(!OutputFilter::default().is_active());
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn regex() {
+        let levels = filter("^\\[(WARN|ERROR)\\]", true);
+        assert!(levels.matches("[ERROR][gfx] lost"));
+        assert!(!levels.matches("[warn][gfx] slow"));
+        let invalid = filter("(", true);
+        assert!(!invalid.is_active());
+        assert!(invalid.error().is_some());
+        assert!(invalid.matches("anything"));
+    }
+
+    #[test]
+    fn rows_follow_new_and_dropped_lines() {
+        let mut console = ColoredConsole::default();
+        console.write("a1\nb1\na2\n");
+        let mut filter = filter("a", false);
+        assert_eq!(rows(&mut filter, &console), ["a1", "a2"]);
+
+        console.write("b2\nb");
+        assert_eq!(rows(&mut filter, &console), ["a1", "a2"]);
+        console.write("a3\n");
+        assert_eq!(rows(&mut filter, &console), ["a1", "a2", "ba3"]);
+
+        console.truncate_lines(3);
+        assert_eq!(rows(&mut filter, &console), ["a2", "ba3"]);
+        let mut earlier = ColoredConsole::default();
+        earlier.write("a0\n");
+        console.prepend(earlier);
+        assert_eq!(rows(&mut filter, &console), ["a0", "a2", "ba3"]);
+
+        filter.text = ImString::new("b");
+        filter.update();
+        assert_eq!(rows(&mut filter, &console), ["b2", "ba3"]);
+        console.clear();
+        console.write("b3\n");
+        assert_eq!(rows(&mut filter, &console), ["b3"]);
+    }
+}
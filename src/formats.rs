@@ -0,0 +1,551 @@
+use crate::{ConsoleError, CvarExt, IConsoleExt, IVisitExt, VisitMutExt};
+use serde::de::{self, Deserialize, Deserializer, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use serde::ser::{Serialize, SerializeMap, Serializer};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+
+/// Structured file formats the property tree can be exported to and imported from.
+///
+/// Each one is enabled by the matching `format-*` feature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    #[cfg(feature = "format-json")]
+    Json,
+    #[cfg(feature = "format-ron")]
+    Ron,
+    #[cfg(feature = "format-toml")]
+    Toml,
+}
+
+impl Format {
+    /// The format matching the extension of `path`, if it is enabled.
+    pub fn from_path(path: &Path) -> Option<Format> {
+        match path.extension()?.to_str()? {
+            #[cfg(feature = "format-json")]
+            "json" => Some(Format::Json),
+            #[cfg(feature = "format-ron")]
+            "ron" => Some(Format::Ron),
+            #[cfg(feature = "format-toml")]
+            "toml" => Some(Format::Toml),
+            _ => None,
+        }
+    }
+
+    /// Extensions of the enabled formats, for error messages
+    fn extensions() -> Vec<&'static str> {
+        vec![
+            #[cfg(feature = "format-json")]
+            "json",
+            #[cfg(feature = "format-ron")]
+            "ron",
+            #[cfg(feature = "format-toml")]
+            "toml",
+        ]
+    }
+}
+
+/// Property values nested by the `.` separated parts of their path
+enum Node {
+    Table(BTreeMap<String, Node>),
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Str(String),
+    /// Anything a property can't be set to, eg. a list. Only read by `import`.
+    Invalid(&'static str),
+}
+
+impl Node {
+    /// Keep values that look like numbers or booleans typed, as long as they read back the same.
+    fn scalar(value: String) -> Node {
+        if let Ok(b) = value.parse::<bool>() {
+            return Node::Bool(b);
+        }
+        if let Ok(i) = value.parse::<i64>() {
+            if i.to_string() == value {
+                return Node::Int(i);
+            }
+        }
+        if let Ok(f) = value.parse::<f64>() {
+            if f.is_finite() && f.to_string() == value {
+                return Node::Float(f);
+            }
+        }
+        Node::Str(value)
+    }
+
+    /// Flatten back to `(path, value)` entries
+    fn flatten(self, path: &str, out: &mut Vec<Entry>) {
+        let value = match self {
+            Node::Table(table) => {
+                for (key, node) in table {
+                    if path.is_empty() {
+                        node.flatten(&key, out);
+                    } else {
+                        node.flatten(&format!("{}.{}", path, key), out);
+                    }
+                }
+                return;
+            }
+            Node::Bool(b) => Ok(b.to_string()),
+            Node::Int(i) => Ok(i.to_string()),
+            Node::Float(f) => Ok(f.to_string()),
+            Node::Str(s) => Ok(s),
+            Node::Invalid(what) => Err(what),
+        };
+        out.push((path.to_string(), value));
+    }
+}
+
+impl Serialize for Node {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Node::Table(table) => {
+                // TOML needs plain values ahead of nested tables
+                let (values, tables): (Vec<_>, Vec<_>) = table
+                    .iter()
+                    .partition(|(_, node)| !matches!(node, Node::Table(_)));
+                let mut map = serializer.serialize_map(Some(table.len()))?;
+                for (key, node) in values.into_iter().chain(tables) {
+                    map.serialize_entry(key, node)?;
+                }
+                map.end()
+            }
+            Node::Bool(b) => serializer.serialize_bool(*b),
+            Node::Int(i) => serializer.serialize_i64(*i),
+            Node::Float(f) => serializer.serialize_f64(*f),
+            Node::Str(s) => serializer.serialize_str(s),
+            Node::Invalid(what) => Err(serde::ser::Error::custom(what)),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Node {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Node, D::Error> {
+        deserializer.deserialize_any(NodeVisitor)
+    }
+}
+
+struct NodeVisitor;
+
+impl<'de> Visitor<'de> for NodeVisitor {
+    type Value = Node;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a table of property values")
+    }
+
+    fn visit_bool<E: de::Error>(self, v: bool) -> Result<Node, E> {
+        Ok(Node::Bool(v))
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<Node, E> {
+        Ok(Node::Int(v))
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<Node, E> {
+        Ok(Node::Str(v.to_string()))
+    }
+
+    fn visit_f64<E: de::Error>(self, v: f64) -> Result<Node, E> {
+        Ok(Node::Float(v))
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Node, E> {
+        Ok(Node::Str(v.to_string()))
+    }
+
+    fn visit_char<E: de::Error>(self, v: char) -> Result<Node, E> {
+        Ok(Node::Str(v.to_string()))
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Node, E> {
+        Ok(Node::Invalid("expected a value, found nothing"))
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<Node, E> {
+        self.visit_unit()
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Node, D::Error> {
+        deserializer.deserialize_any(self)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Node, A::Error> {
+        while seq.next_element::<IgnoredAny>()?.is_some() {}
+        Ok(Node::Invalid("expected a value, found a list"))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Node, A::Error> {
+        let mut table = BTreeMap::new();
+        while let Some((Key(key), node)) = map.next_entry::<Key, Node>()? {
+            table.insert(key, node);
+        }
+        Ok(Node::Table(table))
+    }
+}
+
+/// A table key. RON can only read the fields of `(name: value)` structs through
+/// `deserialize_any` or `deserialize_identifier`, and only the first also reads `{"name": value}`.
+struct Key(String);
+
+impl<'de> Deserialize<'de> for Key {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Key, D::Error> {
+        deserializer.deserialize_any(KeyVisitor)
+    }
+}
+
+struct KeyVisitor;
+
+impl<'de> Visitor<'de> for KeyVisitor {
+    type Value = Key;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a property name")
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Key, E> {
+        Ok(Key(v.to_string()))
+    }
+}
+
+/// Every property in `root`, nested by path and written in `format`.
+pub fn export(root: &mut dyn IVisitExt, format: Format) -> Result<String, ConsoleError> {
+    export_from(
+        &mut VisitMutExt(|f, console| root.visit_mut_ext(f, console)),
+        format,
+    )
+}
+
+fn export_from(root: &mut dyn cvar::IVisit, format: Format) -> Result<String, ConsoleError> {
+    let mut tree = BTreeMap::new();
+    // cvar allows a property and a list with the same name, but they can't be nested that way
+    let mut conflict = None;
+    cvar::console::walk(root, |path, node| {
+        if conflict.is_some() {
+            return;
+        }
+        if let cvar::NodeMut::Prop(prop) = node.as_node_mut() {
+            let mut parts = path.split('.').collect::<Vec<_>>();
+            let name = parts.pop().unwrap_or_default();
+            let mut table = &mut tree;
+            for (i, part) in parts.iter().enumerate() {
+                let node = table
+                    .entry(part.to_string())
+                    .or_insert_with(|| Node::Table(BTreeMap::new()));
+                table = match node {
+                    Node::Table(table) => table,
+                    _ => {
+                        conflict = Some(parts[..=i].join("."));
+                        return;
+                    }
+                };
+            }
+            if table
+                .insert(name.to_string(), Node::scalar(prop.get()))
+                .is_some()
+            {
+                conflict = Some(path.to_string());
+            }
+        }
+    });
+    if let Some(path) = conflict {
+        return Err(ConsoleError::Custom(
+            format!("{} is used by more than one property or list", path).into(),
+        ));
+    }
+
+    let tree = Node::Table(tree);
+    let out = match format {
+        #[cfg(feature = "format-json")]
+        Format::Json => serde_json::to_string_pretty(&tree).map_err(|e| e.to_string()),
+        #[cfg(feature = "format-ron")]
+        Format::Ron => ron::ser::to_string_pretty(&tree, ron::ser::PrettyConfig::default())
+            .map_err(|e| e.to_string()),
+        #[cfg(feature = "format-toml")]
+        Format::Toml => toml::to_string_pretty(&tree).map_err(|e| e.to_string()),
+    };
+    out.map_err(|e| ConsoleError::Custom(e.into()))
+}
+
+/// A property's path and value, or why the value can't be set
+type Entry = (String, Result<String, &'static str>);
+
+/// Set every property in `text`, nested by path and written in `format`.
+///
+/// Returns the error of each entry that could not be set, by path, or an error if `text` could
+/// not be parsed at all.
+pub fn import(
+    root: &mut dyn IVisitExt,
+    format: Format,
+    text: &str,
+) -> Result<Vec<(String, ConsoleError)>, ConsoleError> {
+    import_into(
+        &mut VisitMutExt(|f, console| root.visit_mut_ext(f, console)),
+        format,
+        text,
+    )
+}
+
+fn import_into<T: cvar::IVisit>(
+    root: &mut T,
+    format: Format,
+    text: &str,
+) -> Result<Vec<(String, ConsoleError)>, ConsoleError> {
+    Ok(set_entries(root, parse_entries(format, text)?))
+}
+
+/// The `(path, value)` pairs in `text`, or why a value can't be set
+fn parse_entries(format: Format, text: &str) -> Result<Vec<Entry>, ConsoleError> {
+    let parsed: Result<Node, String> = match format {
+        #[cfg(feature = "format-json")]
+        Format::Json => serde_json::from_str(text).map_err(|e| e.to_string()),
+        #[cfg(feature = "format-ron")]
+        Format::Ron => ron::de::from_str(text).map_err(|e| e.to_string()),
+        #[cfg(feature = "format-toml")]
+        Format::Toml => toml::from_str(text).map_err(|e| e.to_string()),
+    };
+    match parsed {
+        Ok(node @ Node::Table(_)) => {
+            let mut entries = vec![];
+            node.flatten("", &mut entries);
+            Ok(entries)
+        }
+        Ok(_) => Err(ConsoleError::InvalidValue(
+            "expected a table of property values".to_string(),
+        )),
+        Err(e) => Err(ConsoleError::InvalidValue(e)),
+    }
+}
+
+/// Set each entry, returning the errors of those that could not be set
+fn set_entries<T: cvar::IVisit>(root: &mut T, entries: Vec<Entry>) -> Vec<(String, ConsoleError)> {
+    let mut errors = vec![];
+    for (path, value) in entries {
+        let result = match value {
+            Ok(value) => root.set(&path, &value).0,
+            Err(what) => Err(ConsoleError::InvalidValue(what.to_string())),
+        };
+        if let Err(e) = result {
+            errors.push((path, e));
+        }
+    }
+    errors
+}
+
+impl<F: FnMut(&mut dyn FnMut(&mut dyn cvar::INode), &mut dyn IConsoleExt)> VisitMutExt<F> {
+    /// The file named by `args` and its format, or a usage error naming the enabled formats
+    fn file_arg<'a>(cmd: &str, args: &[&'a str]) -> Result<(&'a str, Format), ConsoleError> {
+        match args {
+            [file] => Format::from_path(Path::new(file)).map(|format| (*file, format)),
+            _ => None,
+        }
+        .ok_or_else(|| {
+            ConsoleError::InvalidUsage(format!("{} <file.{}>", cmd, Format::extensions().join("|")))
+        })
+    }
+
+    pub fn cmd_export(&mut self, args: &[&str], console: &mut dyn IConsoleExt) {
+        let out = Self::file_arg("export", args).and_then(|(file, format)| {
+            let text = export_from(self, format)?;
+            std::fs::write(file, text)
+                .map(|_| format!("Exported every property to {}", file))
+                .map_err(|e| ConsoleError::Custom(format!("{}: {}", file, e).into()))
+        });
+        console.write_result(out.into());
+    }
+
+    /// `import`, reporting each entry that could not be set
+    pub fn cmd_import(&mut self, args: &[&str], console: &mut dyn IConsoleExt) {
+        let (file, format) = match Self::file_arg("import", args) {
+            Ok(arg) => arg,
+            Err(e) => return console.write_result(e.into()),
+        };
+        let text = match std::fs::read_to_string(file) {
+            Ok(text) => text,
+            Err(e) => {
+                let e = ConsoleError::Custom(format!("{}: {}", file, e).into());
+                return console.write_result(e.into());
+            }
+        };
+
+        let before = self.values();
+        let errors = match parse_entries(format, &text) {
+            Ok(entries) => {
+                for (path, _) in &entries {
+                    self.add_log_filter(path);
+                }
+                set_entries(self, entries)
+            }
+            Err(e) => {
+                console.write_colored([0.6, 0.6, 0.6, 1.], &format!("{}: ", file));
+                return console.write_result(e.into());
+            }
+        };
+        let from = self.changes.len();
+        self.record_changes(before);
+        for change in &self.changes[from..] {
+            self.origins.set(&change.path, self.layer, &self.source);
+        }
+
+        let failed = errors.len();
+        for (path, e) in errors {
+            console.write_colored([0.6, 0.6, 0.6, 1.], &format!("{}: {}: ", file, path));
+            console.write_result(e.into());
+        }
+        let out = if failed == 0 {
+            Ok(format!("Imported {}", file))
+        } else {
+            Err(ConsoleError::InvalidValue(format!(
+                "{} entries in {} could not be set",
+                failed, file
+            )))
+        };
+        console.write_result(out.into());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Ball {
+        speed: f32,
+        label: String,
+    }
+
+    impl cvar::IVisit for Ball {
+        fn visit_mut(&mut self, f: &mut dyn FnMut(&mut dyn cvar::INode)) {
+            f(&mut cvar::Property("speed", "", &mut self.speed, 0.));
+            f(&mut cvar::Property(
+                "label",
+                "",
+                &mut self.label,
+                String::new(),
+            ));
+        }
+    }
+
+    struct Config {
+        width: i32,
+        on: bool,
+        ball: Ball,
+    }
+
+    impl cvar::IVisit for Config {
+        fn visit_mut(&mut self, f: &mut dyn FnMut(&mut dyn cvar::INode)) {
+            f(&mut cvar::Property("width", "", &mut self.width, 0));
+            f(&mut cvar::Property("on", "", &mut self.on, false));
+            f(&mut cvar::List("ball", "", &mut self.ball));
+        }
+    }
+
+    fn config() -> Config {
+        Config {
+            width: 0,
+            on: false,
+            ball: Ball {
+                speed: 0.,
+                label: String::new(),
+            },
+        }
+    }
+
+    fn formats() -> Vec<Format> {
+        vec![
+            #[cfg(feature = "format-json")]
+            Format::Json,
+            #[cfg(feature = "format-ron")]
+            Format::Ron,
+            #[cfg(feature = "format-toml")]
+            Format::Toml,
+        ]
+    }
+
+    #[test]
+    fn round_trip() {
+        for format in formats() {
+            let mut from = Config {
+                width: 120,
+                on: true,
+                ball: Ball {
+                    speed: 2.5,
+                    label: "007".to_string(),
+                },
+            };
+            let text = export_from(&mut from, format).unwrap();
+            let mut to = config();
+            assert!(import_into(&mut to, format, &text).unwrap().is_empty());
+            assert_eq!((to.width, to.on), (120, true), "{:?}", format);
+            assert_eq!((to.ball.speed, to.ball.label.as_str()), (2.5, "007"));
+        }
+    }
+
+    #[cfg(feature = "format-json")]
+    #[test]
+    fn import_errors() {
+        let mut to = config();
+        let text = r#"{"width": "wide", "on": [true], "ball": {"speed": 3}, "missing": 1}"#;
+        let errors = import_into(&mut to, Format::Json, text).unwrap();
+        let paths = errors
+            .iter()
+            .map(|(path, _)| path.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(paths, ["missing", "on", "width"]);
+        assert_eq!(to.ball.speed, 3.);
+        assert!(import_into(&mut to, Format::Json, "{").is_err());
+        assert!(import_into(&mut to, Format::Json, "1").is_err());
+    }
+
+    #[cfg(feature = "format-ron")]
+    #[test]
+    fn import_ron_structs() {
+        let mut to = config();
+        let text = "(width: 5, ball: (speed: 1.5, label: \"x\"))";
+        assert!(import_into(&mut to, Format::Ron, text).unwrap().is_empty());
+        assert_eq!(
+            (to.width, to.ball.speed, to.ball.label.as_str()),
+            (5, 1.5, "x")
+        );
+    }
+
+    struct Conflict {
+        prop_first: bool,
+        ball: Ball,
+        n: i32,
+    }
+
+    impl cvar::IVisit for Conflict {
+        fn visit_mut(&mut self, f: &mut dyn FnMut(&mut dyn cvar::INode)) {
+            if self.prop_first {
+                f(&mut cvar::Property("ball", "", &mut self.n, 0));
+            }
+            f(&mut cvar::List("ball", "", &mut self.ball));
+            if !self.prop_first {
+                f(&mut cvar::Property("ball", "", &mut self.n, 0));
+            }
+        }
+    }
+
+    #[test]
+    fn export_conflicts() {
+        for prop_first in [true, false] {
+            let mut conflict = Conflict {
+                prop_first,
+                ball: Ball {
+                    speed: 0.,
+                    label: String::new(),
+                },
+                n: 0,
+            };
+            for format in formats() {
+                let e = export_from(&mut conflict, format).unwrap_err();
+                assert_eq!(
+                    e.to_string(),
+                    "ball is used by more than one property or list"
+                );
+            }
+        }
+    }
+}
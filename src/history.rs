@@ -0,0 +1,280 @@
+use crate::{ConsoleError, ConsoleResult, IConsoleExt};
+use imgui::ImString;
+use std::io;
+use std::path::PathBuf;
+
+/// Previously entered commands, optionally persisted to a file between sessions.
+pub(crate) struct History {
+    entries: Vec<String>,
+    limit: usize,
+    file: Option<PathBuf>,
+    /// Entry currently shown while browsing with up/down
+    browsing: Option<usize>,
+    /// Whatever was typed before browsing started, restored when scrolling back down
+    draft: String,
+}
+
+impl History {
+    pub fn new(limit: usize) -> Self {
+        History {
+            entries: vec![],
+            limit,
+            file: None,
+            browsing: None,
+            draft: String::new(),
+        }
+    }
+
+    pub fn set_limit(&mut self, limit: usize) {
+        self.limit = limit;
+        self.truncate();
+    }
+
+    /// Load entries from `path`, and save back to it whenever a command is added.
+    ///
+    /// A missing file is not an error, it will be created on the first command.
+    pub fn set_file(&mut self, path: PathBuf) -> io::Result<()> {
+        let loaded = match std::fs::read_to_string(&path) {
+            Ok(contents) => contents.lines().map(str::to_string).collect(),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => vec![],
+            Err(e) => return Err(e),
+        };
+        self.file = Some(path);
+        for line in loaded {
+            self.add(line);
+        }
+        Ok(())
+    }
+
+    /// Record a command. Blank lines and repeats of the previous command are skipped.
+    pub fn push(&mut self, line: &str) -> io::Result<()> {
+        self.browsing = None;
+        self.draft.clear();
+        if self.add(line.trim().to_string()) {
+            self.save()
+        } else {
+            Ok(())
+        }
+    }
+
+    fn add(&mut self, line: String) -> bool {
+        if line.is_empty() || self.entries.last() == Some(&line) {
+            return false;
+        }
+        self.entries.push(line);
+        self.truncate();
+        true
+    }
+
+    fn truncate(&mut self) {
+        if self.entries.len() > self.limit {
+            let excess = self.entries.len() - self.limit;
+            self.entries.drain(..excess);
+        }
+    }
+
+    fn save(&self) -> io::Result<()> {
+        if let Some(path) = &self.file {
+            let mut contents = self.entries.join("\n");
+            contents.push('\n');
+            std::fs::write(path, contents)?;
+        }
+        Ok(())
+    }
+
+    pub fn clear(&mut self) -> io::Result<()> {
+        self.entries.clear();
+        self.browsing = None;
+        self.save()
+    }
+
+    /// Step back to an older entry. `current` is kept as the draft when browsing starts.
+    pub fn prev(&mut self, current: &str) -> Option<&str> {
+        let index = match self.browsing {
+            Some(0) => 0,
+            Some(i) => i - 1,
+            None => {
+                self.draft = current.to_string();
+                self.entries.len().checked_sub(1)?
+            }
+        };
+        self.browsing = Some(index);
+        Some(&self.entries[index])
+    }
+
+    /// Step forward to a newer entry, ending back at the draft.
+    pub fn next(&mut self) -> Option<&str> {
+        let index = self.browsing? + 1;
+        if index < self.entries.len() {
+            self.browsing = Some(index);
+            Some(&self.entries[index])
+        } else {
+            self.browsing = None;
+            Some(&self.draft)
+        }
+    }
+
+    pub fn get(&self, index: usize) -> Option<&str> {
+        self.entries.get(index).map(String::as_str)
+    }
+
+    /// Find the newest entry containing `query`, only looking before `before` if given.
+    pub fn search(&self, query: &str, before: Option<usize>) -> Option<usize> {
+        let end = before.unwrap_or(self.entries.len()).min(self.entries.len());
+        self.entries[..end]
+            .iter()
+            .rposition(|entry| entry.contains(query))
+    }
+
+    /// Expand a `!!`, `!n` or `!-n` line into the command it refers to.
+    ///
+    /// Returns `None` if the line is not a history reference.
+    pub fn expand(&self, line: &str) -> Result<Option<String>, ConsoleError> {
+        let line = line.trim();
+        if !line.starts_with('!') {
+            return Ok(None);
+        }
+
+        let reference = &line[1..];
+        let index = if reference == "!" {
+            self.entries.len().checked_sub(1)
+        } else if let Some(back) = reference.strip_prefix('-') {
+            match back.parse::<usize>() {
+                Ok(back) => self.entries.len().checked_sub(back),
+                Err(_) => return Ok(None),
+            }
+        } else {
+            match reference.parse::<usize>() {
+                Ok(n) => n.checked_sub(1),
+                Err(_) => return Ok(None),
+            }
+        };
+
+        match index.and_then(|i| self.entries.get(i)) {
+            Some(entry) => Ok(Some(entry.clone())),
+            None => Err(ConsoleError::InvalidValue(format!(
+                "{}: no such history entry",
+                line
+            ))),
+        }
+    }
+
+    pub fn cmd_history(&mut self, args: &[&str], console: &mut dyn IConsoleExt) {
+        let out: ConsoleResult = match args.first() {
+            None => {
+                if self.entries.is_empty() {
+                    ConsoleError::NoResults.into()
+                } else {
+                    self.entries
+                        .iter()
+                        .enumerate()
+                        .map(|(i, entry)| format!("{:>4}  {}\n", i + 1, entry))
+                        .collect::<String>()
+                        .into()
+                }
+            }
+            Some(&"clear") => match self.clear() {
+                Ok(()) => "".into(),
+                Err(e) => ConsoleError::Custom(e.to_string().into()).into(),
+            },
+            Some(_) => ConsoleError::InvalidUsage("history [clear]".to_string()).into(),
+        };
+        console.write_result(out);
+    }
+}
+
+/// State of an in progress reverse incremental search (Ctrl+R)
+pub(crate) struct HistorySearch {
+    pub query: ImString,
+    /// Entry currently matching the query
+    pub found: Option<usize>,
+}
+
+impl HistorySearch {
+    pub fn new() -> Self {
+        HistorySearch {
+            query: ImString::with_capacity(256),
+            found: None,
+        }
+    }
+
+    /// Start over from the newest entry, after the query changed.
+    pub fn restart(&mut self, history: &History) {
+        self.found = history.search(self.query.to_str(), None);
+    }
+
+    /// Move on to the next older match, staying put if there is none.
+    pub fn older(&mut self, history: &History) {
+        match self.found {
+            Some(found) => {
+                if let Some(older) = history.search(self.query.to_str(), Some(found)) {
+                    self.found = Some(older);
+                }
+            }
+            None => self.restart(history),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history(entries: &[&str]) -> History {
+        let mut history = History::new(100);
+        for entry in entries {
+            history.push(entry).unwrap();
+        }
+        history
+    }
+
+    #[test]
+    fn push_skips_blank_and_repeated() {
+        let history = history(&["a", " ", "b", "b ", "a"]);
+        assert_eq!(history.entries, ["a", "b", "a"]);
+    }
+
+    #[test]
+    fn limit_drops_oldest() {
+        let mut history = history(&["a", "b", "c"]);
+        history.set_limit(2);
+        history.push("d").unwrap();
+        assert_eq!(history.entries, ["c", "d"]);
+    }
+
+    #[test]
+    fn browse_back_and_forth() {
+        let mut history = history(&["a", "b"]);
+        assert_eq!(history.next(), None);
+        assert_eq!(history.prev("draft"), Some("b"));
+        assert_eq!(history.prev("b"), Some("a"));
+        assert_eq!(history.prev("a"), Some("a"));
+        assert_eq!(history.next(), Some("b"));
+        assert_eq!(history.next(), Some("draft"));
+        assert_eq!(history.next(), None);
+        assert_eq!(History::new(10).prev("draft"), None);
+    }
+
+    #[test]
+    fn expand_references() {
+        let history = history(&["a", "b", "c"]);
+        assert_eq!(history.expand("width 1").unwrap(), None);
+        assert_eq!(history.expand("!!").unwrap().as_deref(), Some("c"));
+        assert_eq!(history.expand(" !1 ").unwrap().as_deref(), Some("a"));
+        assert_eq!(history.expand("!-2").unwrap().as_deref(), Some("b"));
+        assert_eq!(history.expand("!x").unwrap(), None);
+        assert!(history.expand("!0").is_err());
+        assert!(history.expand("!4").is_err());
+        assert!(history.expand("!-4").is_err());
+        assert!(History::new(10).expand("!!").is_err());
+    }
+
+    #[test]
+    fn search_older_matches() {
+        let history = history(&["width 1", "height 2", "width 3"]);
+        assert_eq!(history.search("width", None), Some(2));
+        assert_eq!(history.search("width", Some(2)), Some(0));
+        assert_eq!(history.search("width", Some(0)), None);
+        assert_eq!(history.search("depth", None), None);
+    }
+}
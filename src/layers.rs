@@ -0,0 +1,61 @@
+use std::collections::HashMap;
+
+/// Where a property value came from, from lowest to highest precedence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Layer {
+    /// The default given to `cvar::Property`
+    Default,
+    /// A config script, such as the autoexec
+    Config,
+    /// An environment variable, see `env_overrides`
+    Environment,
+    /// A command line argument, see `command_line_overrides`
+    CommandLine,
+    /// A command typed into the console
+    Console,
+}
+
+/// The layer a property was set from, and where exactly, eg. `autoexec.cfg:12`.
+#[derive(Debug, Clone)]
+pub struct Origin {
+    pub layer: Layer,
+    pub source: String,
+}
+
+/// Origin of every property set since startup. Anything missing still has its default.
+#[derive(Debug, Default)]
+pub struct Origins(HashMap<String, Origin>);
+
+impl Origins {
+    pub fn get(&self, path: &str) -> Option<&Origin> {
+        self.0.get(path)
+    }
+
+    pub fn layer(&self, path: &str) -> Layer {
+        self.get(path).map_or(Layer::Default, |origin| origin.layer)
+    }
+
+    /// Whether a value from `layer` may replace the current value of `path`.
+    ///
+    /// Automatic sources never override a higher layer, but the console always wins.
+    pub fn allows(&self, path: &str, layer: Layer) -> bool {
+        layer == Layer::Console || layer >= self.layer(path)
+    }
+
+    pub fn set(&mut self, path: &str, layer: Layer, source: &str) {
+        let origin = Origin {
+            layer,
+            source: source.to_string(),
+        };
+        self.0.insert(path.to_string(), origin);
+    }
+
+    /// Forget where `path` was set from, after it was reset to its default.
+    pub fn remove(&mut self, path: &str) {
+        self.0.remove(path);
+    }
+
+    pub fn clear(&mut self) {
+        self.0.clear();
+    }
+}
@@ -4,7 +4,43 @@ mod amethyst;
 #[cfg(feature = "amethyst-system")]
 pub use crate::amethyst::*;
 
-use imgui::{im_str, ImString};
+mod completion;
+mod filter;
+#[cfg(any(
+    feature = "format-json",
+    feature = "format-ron",
+    feature = "format-toml"
+))]
+/// `export` and `import` of the property tree as nested RON, TOML or JSON
+pub mod formats;
+mod history;
+mod layers;
+mod logger;
+mod overrides;
+mod presets;
+mod prompt;
+mod shell;
+mod transaction;
+mod undo;
+mod watch;
+
+pub use crate::layers::*;
+pub use crate::logger::*;
+pub use crate::overrides::*;
+pub use crate::transaction::*;
+pub use crate::undo::Change;
+
+use crate::completion::{complete, CompletionPopup};
+use crate::filter::OutputFilter;
+use crate::history::{History, HistorySearch};
+use crate::presets::Presets;
+use crate::prompt::{input_prompt, PromptEvent};
+use crate::shell::{parse, quote};
+use crate::undo::UndoStack;
+use crate::watch::{Watcher, POLL_INTERVAL};
+use imgui::{im_str, ImGuiInputTextFlags, ImString};
+use std::collections::{BTreeMap, VecDeque};
+use std::path::PathBuf;
 
 /// cvar varients. Used to disambiguate which action to perform when unspecified.
 #[derive(Debug)]
@@ -26,6 +62,8 @@ pub enum ConsoleError {
     InvalidUsage(String),
     NoResults,
     Unimplemented,
+    /// Several paths match when ignoring case, see `IgnoreCase`
+    Ambiguous(Vec<String>),
     Custom(TextSpan),
 }
 
@@ -76,6 +114,9 @@ impl std::fmt::Display for ConsoleError {
             ConsoleError::InvalidUsage(e) => write!(f, "Usage: {}", e),
             ConsoleError::NoResults => f.write_str("No results"),
             ConsoleError::Unimplemented => f.write_str("Unimplemented"),
+            ConsoleError::Ambiguous(paths) => {
+                write!(f, "Ambiguous name, could be: {}", paths.join(", "))
+            }
             ConsoleError::Custom(e) => f.write_str(&e.text),
         }
     }
@@ -85,32 +126,70 @@ impl std::error::Error for ConsoleError {}
 
 /// Extra features for a node. Provides consistent formatting for help entries.
 trait NodeExt {
-    fn details(&mut self, path: &str, out: &mut String);
+    /// Help entry for the node, mentioning where its value was set from if `origin` is given
+    fn details(&mut self, path: &str, origin: Option<&Origin>, out: &mut String);
     fn kind(&mut self) -> CmdType;
+    /// Short summary for completion lists: the value of a property, or the arguments of an action
+    fn hint(&mut self) -> String;
+}
+
+/// Split an action description into its argument usage and the text describing it.
+///
+/// The usage is the first line, if the description has more than one.
+fn split_description(desc: &str) -> (String, String) {
+    let mut parts = desc.split('\n');
+    let part1 = parts.next().unwrap_or("").to_string();
+    let part2 = parts.collect::<Vec<_>>().join("\n");
+    if !part2.is_empty() {
+        (part1, part2)
+    } else {
+        ("".to_string(), part1)
+    }
+}
+
+/// Number of single character edits (insert, delete, replace or swap) to turn `a` into `b`.
+///
+/// Case is ignored, so `HELP` is close to `help`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a = a.to_lowercase().chars().collect::<Vec<_>>();
+    let b = b.to_lowercase().chars().collect::<Vec<_>>();
+    // Rows of the distance table for the last two and current characters of `a`
+    let mut before = vec![0; b.len() + 1];
+    let mut prev = (0..=b.len()).collect::<Vec<_>>();
+    let mut row = vec![0; b.len() + 1];
+    for i in 1..=a.len() {
+        row[0] = i;
+        for j in 1..=b.len() {
+            let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+            row[j] = (prev[j] + 1).min(row[j - 1] + 1).min(prev[j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                row[j] = row[j].min(before[j - 2] + 1);
+            }
+        }
+        std::mem::swap(&mut before, &mut prev);
+        std::mem::swap(&mut prev, &mut row);
+    }
+    prev[b.len()]
 }
 
 impl<'a> NodeExt for dyn cvar::INode + 'a {
-    fn details(&mut self, path: &str, out: &mut String) {
+    fn details(&mut self, path: &str, origin: Option<&Origin>, out: &mut String) {
         let desc = self.description().to_string();
         match self.as_node_mut() {
-            cvar::NodeMut::Prop(prop) => out.push_str(&format!(
-                "{}: {} (Default: {})\n\t{}\n",
-                path,
-                prop.get(),
-                prop.default(),
-                desc
-            )),
+            cvar::NodeMut::Prop(prop) => {
+                out.push_str(&format!(
+                    "{}: {} (Default: {}",
+                    path,
+                    prop.get(),
+                    prop.default()
+                ));
+                if let Some(origin) = origin {
+                    out.push_str(&format!(", set by {}", origin.source));
+                }
+                out.push_str(&format!(")\n\t{}\n", desc));
+            }
             cvar::NodeMut::Action(_) => {
-                let (args, desc) = {
-                    let mut parts = desc.split('\n');
-                    let part1 = parts.next().unwrap_or("").to_string();
-                    let part2 = parts.collect::<Vec<_>>().join("\n");
-                    if !part2.is_empty() {
-                        (part1, part2)
-                    } else {
-                        ("".to_string(), part1)
-                    }
-                };
+                let (args, desc) = split_description(&desc);
 
                 out.push_str(path);
                 if !args.is_empty() {
@@ -129,6 +208,15 @@ impl<'a> NodeExt for dyn cvar::INode + 'a {
             cvar::NodeMut::Action(_) => CmdType::Action,
         }
     }
+
+    fn hint(&mut self) -> String {
+        let desc = self.description().to_string();
+        match self.as_node_mut() {
+            cvar::NodeMut::Prop(prop) => prop.get(),
+            cvar::NodeMut::Action(_) => split_description(&desc).0,
+            cvar::NodeMut::List(_) => "".to_string(),
+        }
+    }
 }
 
 /// Handlers for all the basic builtin console commands.
@@ -141,9 +229,11 @@ pub trait CvarExt {
         -> ConsoleResult;
     fn reset(&mut self, var: &str) -> ConsoleResult;
     fn reset_all(&mut self) -> ConsoleResult;
-    fn find(&mut self, filter: &(dyn Fn(&str) -> bool)) -> ConsoleResult;
+    fn find(&mut self, filter: &dyn Fn(&str) -> bool) -> ConsoleResult;
     fn help(&mut self, var: &str) -> ConsoleResult;
     fn cmdtype(&mut self, var: &str) -> CmdType;
+    /// Up to three known paths close to `var`, for when it could not be found
+    fn suggest(&mut self, var: &str) -> Vec<String>;
 
     /// Turn a textual command into a respective get/set/call action
     fn exec(&mut self, cmd: &str, args: Vec<&str>) -> ConsoleResult;
@@ -178,7 +268,7 @@ impl<T: cvar::IVisit> CvarExt for T {
         args: &[&str],
         console: &mut dyn cvar::IConsole,
     ) -> ConsoleResult {
-        if cvar::console::invoke(&mut *self, cmd, &args, console) {
+        if cvar::console::invoke(&mut *self, cmd, args, console) {
             "".into()
         } else {
             ConsoleError::UnknownCommand.into()
@@ -198,11 +288,11 @@ impl<T: cvar::IVisit> CvarExt for T {
         "OK".into()
     }
 
-    fn find(&mut self, filter: &(dyn Fn(&str) -> bool)) -> ConsoleResult {
+    fn find(&mut self, filter: &dyn Fn(&str) -> bool) -> ConsoleResult {
         let mut out = String::new();
         cvar::console::walk(&mut *self, |path, node| {
             if filter(path) {
-                node.details(path, &mut out);
+                node.details(path, None, &mut out);
             }
         });
 
@@ -216,7 +306,7 @@ impl<T: cvar::IVisit> CvarExt for T {
     fn help(&mut self, var: &str) -> ConsoleResult {
         let mut out = String::new();
         cvar::console::find(&mut *self, var, |node| {
-            node.details(var, &mut out);
+            node.details(var, None, &mut out);
         });
 
         if !out.is_empty() {
@@ -234,19 +324,40 @@ impl<T: cvar::IVisit> CvarExt for T {
         t
     }
 
+    fn suggest(&mut self, var: &str) -> Vec<String> {
+        // Allow roughly one typo for every three characters
+        let max = var.chars().count().div_ceil(3);
+        let mut close = vec![];
+        cvar::console::walk(&mut *self, |path, _| {
+            let distance = edit_distance(var, path);
+            if distance <= max {
+                close.push((distance, path.to_string()));
+            }
+        });
+        close.sort();
+        close.dedup_by(|a, b| a.1 == b.1);
+        close.into_iter().take(3).map(|(_, path)| path).collect()
+    }
+
     fn exec(&mut self, cmd: &str, args: Vec<&str>) -> ConsoleResult {
         match self.cmdtype(cmd) {
             CmdType::Prop => {
-                if let Some(val) = args.get(0) {
+                if let Some(val) = args.first() {
                     self.set(cmd, val)
                 } else {
                     self.get(cmd)
                 }
             }
             CmdType::Action => {
-                let mut out = String::new();
-                self.call(cmd, &args, &mut out);
-                out.into()
+                let mut out = ColoredConsole::default();
+                if let Err(e) = self.call(cmd, &args, &mut out).0 {
+                    return e.into();
+                }
+                if out.failed() {
+                    ConsoleError::Custom(out.text().into()).into()
+                } else {
+                    out.text().into()
+                }
             }
             CmdType::List => self.find(&|path: &str| path.starts_with(cmd)),
             CmdType::NotFound => ConsoleError::UnknownCommand.into(),
@@ -254,6 +365,105 @@ impl<T: cvar::IVisit> CvarExt for T {
     }
 }
 
+/// Case-insensitive version of a config's `CvarExt` commands
+///
+/// Paths are matched exactly when possible, and otherwise ignoring case. If several paths only
+/// differ by case, the command fails with `ConsoleError::Ambiguous` instead of picking one.
+pub struct IgnoreCase<'a, T>(pub &'a mut T);
+
+impl<T: cvar::IVisit> IgnoreCase<'_, T> {
+    /// The path `var` refers to. Unknown names are returned as is.
+    pub fn resolve(&mut self, var: &str) -> Result<String, ConsoleError> {
+        let mut exact = false;
+        let mut matches = vec![];
+        cvar::console::walk(&mut *self.0, |path, _| {
+            if path == var {
+                exact = true;
+            } else if path.to_lowercase() == var.to_lowercase() {
+                matches.push(path.to_string());
+            }
+        });
+        matches.sort();
+        matches.dedup();
+
+        if exact || matches.is_empty() {
+            Ok(var.to_string())
+        } else if matches.len() == 1 {
+            Ok(matches.remove(0))
+        } else {
+            Err(ConsoleError::Ambiguous(matches))
+        }
+    }
+}
+
+impl<T: cvar::IVisit> CvarExt for IgnoreCase<'_, T> {
+    fn get(&mut self, var: &str) -> ConsoleResult {
+        match self.resolve(var) {
+            Ok(var) => self.0.get(&var),
+            Err(e) => e.into(),
+        }
+    }
+
+    fn set(&mut self, var: &str, val: &str) -> ConsoleResult {
+        match self.resolve(var) {
+            Ok(var) => self.0.set(&var, val),
+            Err(e) => e.into(),
+        }
+    }
+
+    fn call(
+        &mut self,
+        cmd: &str,
+        args: &[&str],
+        console: &mut dyn cvar::IConsole,
+    ) -> ConsoleResult {
+        match self.resolve(cmd) {
+            Ok(cmd) => self.0.call(&cmd, args, console),
+            Err(e) => e.into(),
+        }
+    }
+
+    fn reset(&mut self, var: &str) -> ConsoleResult {
+        match self.resolve(var) {
+            Ok(var) => self.0.reset(&var),
+            Err(e) => e.into(),
+        }
+    }
+
+    fn reset_all(&mut self) -> ConsoleResult {
+        self.0.reset_all()
+    }
+
+    fn find(&mut self, filter: &dyn Fn(&str) -> bool) -> ConsoleResult {
+        self.0.find(filter)
+    }
+
+    fn help(&mut self, var: &str) -> ConsoleResult {
+        match self.resolve(var) {
+            Ok(var) => self.0.help(&var),
+            Err(e) => e.into(),
+        }
+    }
+
+    fn cmdtype(&mut self, var: &str) -> CmdType {
+        match self.resolve(var) {
+            Ok(var) => self.0.cmdtype(&var),
+            Err(_) => CmdType::NotFound,
+        }
+    }
+
+    fn suggest(&mut self, var: &str) -> Vec<String> {
+        self.0.suggest(var)
+    }
+
+    fn exec(&mut self, cmd: &str, args: Vec<&str>) -> ConsoleResult {
+        match self.resolve(cmd) {
+            Ok(cmd) => self.0.exec(&cmd, args),
+            Err(e) => e.into(),
+        }
+    }
+}
+
 /// A piece of text with attached metadata such as color
 ///
 /// Construct manually, or convert from a `String` / `ConsoleError` if you want the default color.
@@ -291,6 +501,29 @@ pub trait IConsoleExt: cvar::IConsole {
     fn write(&mut self, text: &str);
     fn write_result(&mut self, result: ConsoleResult);
     fn write_colored(&mut self, c: [f32; 4], t: &str);
+
+    /// Offer completions for argument `arg` (counting from 0) of the action or property at `path`.
+    ///
+    /// Call this from `IVisitExt::visit_mut_ext` next to the matching `f(...)`, eg.
+    /// `console.complete("color", 0, Completions::Values(&["white", "red", "blue"]))`.
+    /// Only consoles that are completing use it, everything else ignores it.
+    fn complete(&mut self, _path: &str, _arg: usize, _completions: Completions) {}
+
+    /// Record that an action set a property, as if it was set from the console.
+    ///
+    /// `help` then shows where the value came from, and `undo` can revert it. Consoles that don't
+    /// track changes ignore it.
+    fn changed(&mut self, _change: Change) {}
+}
+
+/// Source of completion candidates for an argument, see `IConsoleExt::complete`
+///
+/// Properties that implement `cvar::IProperty::values` are completed without this.
+pub enum Completions<'a> {
+    /// A fixed list, such as the names of an enum
+    Values(&'a [&'a str]),
+    /// Candidates computed when needed, given the partially typed word
+    With(&'a dyn Fn(&str) -> Vec<String>),
 }
 
 impl std::fmt::Display for TextSpan {
@@ -303,8 +536,18 @@ impl std::fmt::Display for TextSpan {
 ///
 /// See `IConsoleExt` for extra methods.
 /// This will usually be managed by a ConsoleWindow.
+#[derive(Default)]
 pub struct ColoredConsole {
-    buf: Vec<TextSpan>,
+    /// Spans of each line, without the newlines
+    lines: VecDeque<Vec<TextSpan>>,
+    /// Whether the last line is still waiting for its newline
+    open: bool,
+    /// Lines removed so far, by `truncate_lines` or otherwise, see `dropped`
+    dropped: usize,
+    /// Whether an error was written, see `failed`
+    failed: bool,
+    /// Properties set by actions, see `IConsoleExt::changed`
+    changes: Vec<Change>,
 }
 
 impl ColoredConsole {
@@ -312,7 +555,29 @@ impl ColoredConsole {
     where
         S: Into<TextSpan>,
     {
-        self.buf.push(text.into());
+        let span = text.into();
+        for (i, part) in span.text.split('\n').enumerate() {
+            if i > 0 {
+                // The newline before `part` closed the last line, or ended an empty one
+                if !self.open {
+                    self.lines.push_back(vec![]);
+                }
+                self.open = false;
+            }
+            if part.is_empty() {
+                continue;
+            }
+            if !self.open {
+                self.lines.push_back(vec![]);
+                self.open = true;
+            }
+            if let Some(line) = self.lines.back_mut() {
+                line.push(TextSpan {
+                    color: span.color,
+                    text: part.to_string(),
+                });
+            }
+        }
     }
 
     pub fn writeln<S>(&mut self, text: S)
@@ -322,16 +587,101 @@ impl ColoredConsole {
         let mut span = text.into();
         span.text = span.text.trim_end().to_string();
         if !span.text.is_empty() {
-            span.text.push_str("\n");
+            span.text.push('\n');
             self.write(span);
         }
     }
+
+    fn clear(&mut self) {
+        self.dropped += self.lines.len();
+        self.lines.clear();
+        self.open = false;
+        self.failed = false;
+    }
+
+    /// Whether an error was written, through `write_error` or an `Err` passed to `write_result`.
+    pub fn failed(&self) -> bool {
+        self.failed
+    }
+
+    fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    /// Remove everything written so far, as spans that end each line with a newline again.
+    fn take_spans(&mut self) -> Vec<TextSpan> {
+        let open = std::mem::replace(&mut self.open, false);
+        let count = self.lines.len();
+        self.dropped += count;
+        let mut spans = vec![];
+        for (i, mut line) in self.lines.drain(..).enumerate() {
+            if open && i + 1 == count {
+                spans.extend(line);
+                break;
+            }
+            match line.last_mut() {
+                Some(last) => last.text.push('\n'),
+                None => line.push("\n".into()),
+            }
+            spans.extend(line);
+        }
+        spans
+    }
+
+    /// Put everything in `earlier` ahead of what was written so far.
+    ///
+    /// Every line is numbered anew, as if the old ones were dropped.
+    fn prepend(&mut self, mut earlier: ColoredConsole) {
+        earlier.dropped = self.dropped + self.lines.len();
+        earlier.lines.extend(self.lines.drain(..));
+        earlier.open = self.open;
+        earlier.failed |= self.failed;
+        *self = earlier;
+    }
+
+    /// Drop the oldest lines until at most `max` are left.
+    fn truncate_lines(&mut self, max: usize) {
+        while self.lines.len() > max {
+            self.lines.pop_front();
+            self.dropped += 1;
+        }
+        if self.lines.is_empty() {
+            self.open = false;
+        }
+    }
+
+    /// Lines removed so far, so line `i` is the `dropped() + i`th line ever written.
+    ///
+    /// Lines written ahead of the others by `prepend` are numbered after them instead.
+    fn dropped(&self) -> usize {
+        self.dropped
+    }
+
+    fn line_count(&self) -> usize {
+        self.lines.len()
+    }
+
+    /// Text of line `i`, without the newline
+    fn line_text(&self, i: usize) -> String {
+        self.lines[i]
+            .iter()
+            .map(|span| span.text.as_str())
+            .collect()
+    }
+
+    /// Text of every line, joined by newlines
+    fn text(&self) -> String {
+        (0..self.lines.len())
+            .map(|i| self.line_text(i))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
 }
 
 impl IConsoleExt for ColoredConsole {
     fn write(&mut self, text: &str) {
         use std::fmt::Write;
-        let _ = self.write_str(&text);
+        let _ = self.write_str(text);
     }
 
     fn write_result(&mut self, result: ConsoleResult) {
@@ -352,6 +702,10 @@ impl IConsoleExt for ColoredConsole {
             color: c,
         });
     }
+
+    fn changed(&mut self, change: Change) {
+        self.changes.push(change);
+    }
 }
 
 impl std::fmt::Write for ColoredConsole {
@@ -363,6 +717,7 @@ impl std::fmt::Write for ColoredConsole {
 
 impl cvar::IConsole for ColoredConsole {
     fn write_error(&mut self, err: &(dyn std::error::Error + 'static)) {
+        self.failed = true;
         self.writeln(ConsoleError::Custom(err.to_string().into()));
     }
 }
@@ -374,16 +729,125 @@ impl cvar::IConsole for ColoredConsole {
 pub struct ConsoleWindow {
     console: ColoredConsole,
     prompt: ImString,
-    //history: Vec<String>,
+    history: History,
+    presets: Presets,
+    undo: UndoStack,
+    search: Option<HistorySearch>,
+    /// Give keyboard focus to the prompt (or search box) on the next frame
+    refocus: bool,
+    /// Where to put the cursor once the prompt is active again
+    move_cursor: Option<usize>,
+    /// Key of the undo or redo shortcut that fired, until it is released
+    shortcut_held: Option<u32>,
+    popup: CompletionPopup,
+    filter: OutputFilter,
+    ignore_case: bool,
+    /// Script to run on the next `update`
+    autoexec: Option<PathBuf>,
+    origins: Origins,
+    /// Opened by `begin` and kept between command lines
+    transaction: Option<Transaction>,
+    watch: Option<Watcher>,
+    settings: Settings,
 }
 
 impl ConsoleWindow {
     pub fn new() -> Self {
         ConsoleWindow {
-            console: ColoredConsole { buf: vec![] },
-            prompt: ImString::with_capacity(100),
-            //history: vec![],
+            console: ColoredConsole::default(),
+            prompt: ImString::with_capacity(256),
+            history: History::new(100),
+            presets: Presets::default(),
+            undo: UndoStack::new(100),
+            search: None,
+            refocus: false,
+            move_cursor: None,
+            shortcut_held: None,
+            popup: CompletionPopup::default(),
+            filter: OutputFilter::default(),
+            ignore_case: false,
+            autoexec: None,
+            origins: Origins::default(),
+            transaction: None,
+            watch: None,
+            settings: Settings {
+                log: None,
+                scrollback: Scrollback {
+                    lines: 1000,
+                    default: 1000,
+                },
+            },
+        }
+    }
+
+    /// Keep at most `limit` commands in the history. Defaults to 100.
+    pub fn with_history_limit(mut self, limit: usize) -> Self {
+        self.history.set_limit(limit);
+        self
+    }
+
+    /// Load command history from `path`, and keep it updated as new commands are entered.
+    pub fn with_history_file<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        let path = path.into();
+        if let Err(e) = self.history.set_file(path.clone()) {
+            self.writeln(ConsoleError::Custom(
+                format!("Failed to load history from {}: {}", path.display(), e).into(),
+            ));
         }
+        self
+    }
+
+    /// Keep presets in `dir` between sessions, loading any already saved there.
+    pub fn with_preset_dir<P: Into<PathBuf>>(mut self, dir: P) -> Self {
+        let dir = dir.into();
+        if let Err(e) = self.presets.set_dir(dir.clone()) {
+            self.writeln(ConsoleError::Custom(
+                format!("Failed to load presets from {}: {}", dir.display(), e).into(),
+            ));
+        }
+        self
+    }
+
+    /// Match commands and properties regardless of case, so `HELP` runs `help`. Off by default.
+    pub fn with_ignore_case(mut self, ignore_case: bool) -> Self {
+        self.ignore_case = ignore_case;
+        self
+    }
+
+    /// Run the commands in `path` on the first frame, eg. to apply personal defaults.
+    ///
+    /// Only failing lines are shown. A missing file is silently skipped.
+    pub fn with_autoexec<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.autoexec = Some(path.into());
+        self
+    }
+
+    /// Run the commands in `path` on the first frame, and again whenever the file is saved,
+    /// so values can be tweaked from a text editor while the game runs.
+    ///
+    /// After the first run only lines that are new or changed are run again, followed by a
+    /// summary of what changed. There is no need to also pass the file to `with_autoexec`.
+    pub fn with_watch<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.watch = Some(Watcher::new(path.into(), POLL_INTERVAL));
+        self
+    }
+
+    /// Keep at most `lines` lines of output, dropping the oldest ones first. Defaults to 1000,
+    /// 0 keeps every line. Can also be changed with the `console.scrollback` property.
+    pub fn with_scrollback(mut self, lines: usize) -> Self {
+        self.settings.scrollback = Scrollback {
+            lines,
+            default: lines,
+        };
+        self
+    }
+
+    /// Show the records a `ConsoleLogger` sends to `queue`, colored by level.
+    ///
+    /// Adds the `log.level` and `log.filter.<target>` properties to change what is logged.
+    pub fn with_log(mut self, queue: LogQueue) -> Self {
+        self.settings.log = Some(queue);
+        self
     }
 }
 
@@ -395,7 +859,7 @@ impl Default for ConsoleWindow {
 
 impl ConsoleWindow {
     pub fn clear(&mut self) {
-        self.console.buf.clear();
+        self.console.clear();
     }
 
     pub fn write<S>(&mut self, text: S)
@@ -423,7 +887,222 @@ impl ConsoleWindow {
         });
     }
 
+    /// Set each `(name, value)` pair in `root`, eg. from `command_line_overrides`.
+    ///
+    /// The values are recorded as coming from `source` in `layer`, and properties already set by a
+    /// higher layer are left alone, see `Origins::allows`.
+    /// Failures are written to the console prefixed by `source`. Returns whether all succeeded.
+    pub fn apply_overrides(
+        &mut self,
+        root: &mut dyn IVisitExt,
+        layer: Layer,
+        source: &str,
+        overrides: &[(String, String)],
+    ) -> bool {
+        let history = &mut self.history;
+        let presets = &mut self.presets;
+        let undo = &mut self.undo;
+        let settings = &mut self.settings;
+        let console = &mut self.console;
+        let origins = &mut self.origins;
+        let ignore_case = self.ignore_case;
+        let log = settings.log.clone();
+        let mut root = VisitMutExt(|f, console| {
+            visit_window(history, presets, undo, settings, root, f, console)
+        })
+        .with_ignore_case(ignore_case)
+        .with_log(log);
+
+        let mut ok = true;
+        for (name, value) in overrides {
+            root.add_log_filter(name);
+            let path = root.resolve(name).unwrap_or_else(|_| name.clone());
+            if !origins.allows(&path, layer) {
+                continue;
+            }
+            let result = if ignore_case {
+                IgnoreCase(&mut root).set(name, value)
+            } else {
+                root.set(name, value)
+            };
+            if result.is_ok() {
+                origins.set(&path, layer, source);
+                continue;
+            }
+            ok = false;
+            let unknown = matches!(*result, Err(ConsoleError::UnknownProperty));
+            console.write_colored([0.6, 0.6, 0.6, 1.], &format!("{}: {}: ", source, name));
+            console.write_result(result);
+            if unknown {
+                root.write_suggestions(name, console);
+            }
+        }
+        ok
+    }
+
+    /// Apply overrides from environment variables starting with `prefix`, see `env_overrides`.
+    ///
+    /// Variables that match no property, or more than one, are written as warnings.
+    /// Returns whether every variable was applied.
+    pub fn apply_env_overrides(&mut self, root: &mut dyn IVisitExt, prefix: &str) -> bool {
+        let mut vars = vec![];
+        let mut invalid = false;
+        for (name, value) in std::env::vars_os() {
+            match (name.into_string(), value.into_string()) {
+                (Ok(name), Ok(value)) => vars.push((name, value)),
+                (name, _) => {
+                    let name = name.unwrap_or_else(|name| name.to_string_lossy().into_owned());
+                    if name.starts_with(prefix) {
+                        invalid = true;
+                        self.write_colored(
+                            [1., 1., 0., 1.],
+                            &format!("environment: {} is not valid UTF-8\n", name),
+                        );
+                    }
+                }
+            }
+        }
+        let found = env_overrides(root, prefix, vars);
+        for name in &found.unknown {
+            self.write_colored(
+                [1., 1., 0., 1.],
+                &format!("environment: {} does not match any property\n", name),
+            );
+        }
+        for (name, paths) in &found.ambiguous {
+            self.write_colored(
+                [1., 1., 0., 1.],
+                &format!(
+                    "environment: {} matches more than one property: {}\n",
+                    name,
+                    paths.join(", ")
+                ),
+            );
+        }
+        self.apply_overrides(root, Layer::Environment, "environment", &found.overrides)
+            && found.unknown.is_empty()
+            && found.ambiguous.is_empty()
+            && !invalid
+    }
+
+    /// Work that has to happen every frame, even while the window is hidden.
+    ///
+    /// Called by `build`, so only call this yourself on frames where the window is not built.
+    pub fn update(&mut self, root: &mut dyn IVisitExt) {
+        if let Some(path) = self.autoexec.take() {
+            self.run_autoexec(root, &path);
+        }
+        self.poll_watch(root);
+        if let Some(log) = &self.settings.log {
+            for span in log.drain() {
+                self.console.write(span);
+            }
+        }
+        if self.settings.scrollback.lines > 0 {
+            self.console.truncate_lines(self.settings.scrollback.lines);
+        }
+    }
+
+    fn run_autoexec(&mut self, root: &mut dyn IVisitExt, path: &std::path::Path) {
+        let mut out = ColoredConsole::default();
+        match std::fs::read_to_string(path) {
+            Ok(script) => {
+                self.run_config(root, path, &script, &mut out);
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => out.writeln(ConsoleError::Custom(
+                format!("Failed to run {}: {}", path.display(), e).into(),
+            )),
+        }
+        // Keep the script's errors ahead of the welcome message written at startup
+        self.console.prepend(out);
+    }
+
+    /// Run the lines of the watched file that changed since it was last run, if it was saved.
+    ///
+    /// While a transaction is open the file is left alone, and run once it is committed or rolled
+    /// back.
+    fn poll_watch(&mut self, root: &mut dyn IVisitExt) {
+        if self.transaction.is_some() {
+            return;
+        }
+        let mut watch = match self.watch.take() {
+            Some(watch) => watch,
+            None => return,
+        };
+        let reload = watch.loaded();
+        match watch.poll() {
+            Some(Ok(script)) => {
+                let mut out = ColoredConsole::default();
+                let (changes, kept) = self.run_config(root, watch.path(), &script, &mut out);
+                if reload {
+                    let mut summary = format!(
+                        "Reloaded {}, {} properties changed",
+                        watch.path().display(),
+                        changes.len()
+                    );
+                    if !kept.is_empty() {
+                        summary.push_str(&format!(", {} kept", kept.len()));
+                    }
+                    self.write_colored([0.6, 0.6, 0.6, 1.], &format!("{}\n", summary));
+                    for change in &changes {
+                        self.writeln(format!("{}: {} -> {}", change.path, change.old, change.new));
+                    }
+                    for path in &kept {
+                        let note = kept_note(path, &self.origins);
+                        self.write_colored([0.6, 0.6, 0.6, 1.], &note);
+                    }
+                }
+                for span in out.take_spans() {
+                    self.console.write(span);
+                }
+            }
+            Some(Err(e)) => {
+                let what = if reload { "reload" } else { "load" };
+                self.writeln(ConsoleError::Custom(
+                    format!("Failed to {} {}: {}", what, watch.path().display(), e).into(),
+                ))
+            }
+            None => {}
+        }
+        self.watch = Some(watch);
+    }
+
+    /// Run `script` as the config file at `path`, only writing lines that failed to `out`.
+    ///
+    /// Returns the properties it changed, and those it left alone because a higher layer set them.
+    fn run_config(
+        &mut self,
+        root: &mut dyn IVisitExt,
+        path: &std::path::Path,
+        script: &str,
+        out: &mut ColoredConsole,
+    ) -> (Vec<Change>, Vec<String>) {
+        let name = path.file_name().map_or_else(
+            || path.display().to_string(),
+            |name| name.to_string_lossy().into(),
+        );
+        let history = &mut self.history;
+        let presets = &mut self.presets;
+        let undo = &mut self.undo;
+        let settings = &mut self.settings;
+        let log = settings.log.clone();
+        let mut root = VisitMutExt(|f, console| {
+            visit_window(history, presets, undo, settings, root, f, console)
+        })
+        .with_ignore_case(self.ignore_case)
+        .with_origins(std::mem::take(&mut self.origins))
+        .with_layer(Layer::Config, &name)
+        .with_log(log);
+        root.run_lines(&name, script, out, true);
+        let changes = root.take_changes();
+        let kept = std::mem::take(&mut root.kept);
+        self.origins = root.into_origins();
+        (changes, kept)
+    }
+
     pub fn build(&mut self, ui: &imgui::Ui, window: imgui::Window, root: &mut dyn IVisitExt) {
+        self.update(root);
         window
             .size([520., 600.], imgui::Condition::FirstUseEver)
             .build(ui, move || {
@@ -438,6 +1117,23 @@ impl ConsoleWindow {
                 let clear = ui.button(im_str!("Clear"), [0., 0.]);
                 ui.same_line(0.);
                 let copy = ui.button(im_str!("Copy"), [0., 0.]);
+                ui.same_line(0.);
+                let width = ui.push_item_width(200.);
+                imgui::InputText::new(ui, im_str!("Filter"), &mut self.filter.text).build();
+                width.pop(ui);
+                #[cfg(feature = "regex")]
+                {
+                    ui.same_line(0.);
+                    ui.checkbox(im_str!("Regex"), &mut self.filter.regex);
+                }
+                self.filter.update();
+                if let Some(e) = self.filter.error() {
+                    ui.same_line(0.);
+                    ui.text_colored([1., 0., 0., 1.], "Invalid regex");
+                    if ui.is_item_hovered() {
+                        ui.tooltip_text(e);
+                    }
+                }
                 ui.separator();
 
                 let footer_height_to_reserve = 1.5 * ui.frame_height_with_spacing();
@@ -448,28 +1144,44 @@ impl ConsoleWindow {
                     if clear {
                         self.clear();
                     }
-                    let buf = &mut self.console.buf;
+                    // Lines left by the filter, if it is hiding any
+                    self.filter.update_rows(&self.console);
+                    let console = &self.console;
+                    let filter = &self.filter;
+                    let filtered = filter.is_active();
+
                     if copy {
-                        ui.set_clipboard_text(&ImString::new(
-                            buf.iter()
-                                .map(|l| l.to_string())
-                                .collect::<Vec<String>>()
-                                .join("\n"),
-                        ));
+                        let text = if filtered {
+                            (0..filter.row_count())
+                                .map(|row| console.line_text(filter.row(row)))
+                                .collect::<Vec<_>>()
+                                .join("\n")
+                        } else {
+                            console.text()
+                        };
+                        ui.set_clipboard_text(&ImString::new(text));
                     }
 
                     let style = ui.push_style_var(imgui::StyleVar::ItemSpacing([0., 0.]));
 
-                    for span in buf {
-                        /*if span.text.contains("\r") {
-                            let pos = ui.cursor_pos();
-                            ui.set_cursor_pos([0., pos[1]]);
-                        }*/
-                        ui.text_colored(span.color, &span.text);
-                        if !span.text.contains('\n') {
-                            ui.same_line(0.);
+                    // Every line is one row high, so only the visible ones need drawing
+                    let count = if filtered {
+                        filter.row_count()
+                    } else {
+                        console.line_count()
+                    };
+                    clip_rows(count, ui.text_line_height_with_spacing(), |row| {
+                        let line = &console.lines[if filtered { filter.row(row) } else { row }];
+                        if line.is_empty() {
+                            ui.text("");
                         }
-                    }
+                        for (j, span) in line.iter().enumerate() {
+                            if j > 0 {
+                                ui.same_line(0.);
+                            }
+                            ui.text_colored(span.color, &span.text);
+                        }
+                    });
 
                     style.pop(ui);
 
@@ -479,25 +1191,172 @@ impl ConsoleWindow {
                 });
 
                 ui.separator();
-                let mut reclaim_focus = false;
-                let input = imgui::InputText::new(ui, im_str!("cmd"), &mut self.prompt)
-                    .enter_returns_true(true)
-                    //.callback_completion(true)
-                    //.callback_history(true)
-                    .build();
-                if input {
+                self.build_prompt(ui, root);
+            });
+    }
+
+    fn build_prompt(&mut self, ui: &imgui::Ui, root: &mut dyn IVisitExt) {
+        let focused = ui.is_window_focused_with_flags(imgui::WindowFocusedFlags::CHILD_WINDOWS);
+        if focused && ui.io().key_ctrl && ui.is_key_pressed(letter_key(ui, 'R')) {
+            if self.search.is_none() {
+                self.refocus = true;
+            }
+            let history = &self.history;
+            self.search
+                .get_or_insert_with(HistorySearch::new)
+                .older(history);
+        }
+
+        // Undo shortcuts, unless the prompt has text of its own to undo. They fire once per press,
+        // not again on every frame or key repeat while held down
+        if let Some(key) = self.shortcut_held {
+            if !ui.is_key_down(key) {
+                self.shortcut_held = None;
+            }
+        }
+        if focused
+            && ui.io().key_ctrl
+            && self.search.is_none()
+            && self.prompt.is_empty()
+            && self.shortcut_held.is_none()
+        {
+            for (letter, cmd) in [('Z', "undo"), ('Y', "redo")] {
+                let key = letter_key(ui, letter);
+                if ui.is_key_pressed(key) {
+                    self.shortcut_held = Some(key);
                     self.draw_prompt();
-                    self.write(&format!("{}\n", self.prompt));
-                    self.run_cmd(root, self.prompt.to_string());
+                    self.write(format!("{}\n", cmd));
+                    self.run_line(root, cmd);
+                }
+            }
+        }
+
+        if let Some(search) = &mut self.search {
+            ui.text_colored([0., 1., 1., 1.], "(reverse-i-search)");
+            ui.same_line(0.);
+            let query = search.query.to_string();
+            let width = ui.push_item_width(200.);
+            let accept = imgui::InputText::new(ui, im_str!("##search"), &mut search.query)
+                .enter_returns_true(true)
+                .build();
+            width.pop(ui);
+            if self.refocus {
+                ui.set_keyboard_focus_here(imgui::FocusedWidget::Previous);
+                self.refocus = false;
+            }
+            if search.query.to_str() != query {
+                search.restart(&self.history);
+            }
+
+            let history = &self.history;
+            let found = search.found.and_then(|i| history.get(i));
+            ui.same_line(0.);
+            match found {
+                Some(line) => ui.text(line),
+                None => ui.text_disabled("no match"),
+            }
+
+            let cancel = ui.is_key_pressed(ui.key_index(imgui::Key::Escape))
+                || (ui.io().key_ctrl && ui.is_key_pressed(letter_key(ui, 'G')));
+            if accept || cancel {
+                if let (true, Some(line)) = (accept, found) {
                     self.prompt.clear();
-                    reclaim_focus = true;
+                    self.prompt.push_str(line);
+                    self.move_cursor = Some(line.len());
                 }
+                self.search = None;
+                self.refocus = true;
+            }
+            return;
+        }
 
-                ui.set_item_default_focus();
-                if reclaim_focus {
-                    ui.set_keyboard_focus_here(imgui::FocusedWidget::Previous);
+        let history = &mut self.history;
+        let presets = &mut self.presets;
+        let undo = &mut self.undo;
+        let settings = &mut self.settings;
+        let console = &mut self.console;
+        let move_cursor = &mut self.move_cursor;
+        let popup = &mut self.popup;
+        let mut flags = ImGuiInputTextFlags::EnterReturnsTrue
+            | ImGuiInputTextFlags::CallbackHistory
+            | ImGuiInputTextFlags::CallbackCompletion
+            | ImGuiInputTextFlags::CallbackAlways;
+        if self.prompt.is_empty() {
+            // Ctrl+Z / Ctrl+Y undo property changes then, not text typed before the last command
+            flags |= ImGuiInputTextFlags::NoUndoRedo;
+        }
+        let input = input_prompt(
+            ui,
+            im_str!("cmd"),
+            &mut self.prompt,
+            flags,
+            |event, edit| {
+                let line = match event {
+                    PromptEvent::Up if popup.is_open() => {
+                        popup.select_prev();
+                        None
+                    }
+                    PromptEvent::Down if popup.is_open() => {
+                        popup.select_next();
+                        None
+                    }
+                    PromptEvent::Up => history.prev(edit.text()),
+                    PromptEvent::Down => history.next(),
+                    PromptEvent::Complete => {
+                        let mut root = VisitMutExt(|f, console| {
+                            visit_window(history, presets, undo, settings, root, f, console)
+                        });
+                        let completion = complete(&mut root, edit.text(), edit.cursor());
+                        if let Some(text) = completion.replacement() {
+                            edit.replace(completion.start, completion.end, &text);
+                        }
+                        if completion.candidates.len() > 1 {
+                            console.writeln(TextSpan {
+                                text: completion.names().join("  "),
+                                color: [0.6, 0.6, 0.6, 1.],
+                            });
+                        }
+                        None
+                    }
+                    PromptEvent::Always => {
+                        if let Some(pos) = move_cursor.take() {
+                            edit.set_cursor(pos);
+                        }
+                        let mut root = VisitMutExt(|f, console| {
+                            visit_window(history, presets, undo, settings, root, f, console)
+                        });
+                        popup.update(&mut root, edit.text(), edit.cursor());
+                        None
+                    }
+                };
+                if let Some(line) = line.map(str::to_string) {
+                    edit.set_text(&line);
+                    popup.hide(edit.text(), edit.cursor());
                 }
-            });
+            },
+        );
+        let active = ui.is_item_active();
+        if input {
+            if let Some((line, cursor)) = self.popup.accept() {
+                self.prompt.clear();
+                self.prompt.push_str(&line);
+                self.move_cursor = Some(cursor);
+            } else {
+                self.draw_prompt();
+                self.write(format!("{}\n", self.prompt));
+                self.run_cmd(root, self.prompt.to_string());
+                self.prompt.clear();
+            }
+            self.refocus = true;
+        } else if active && self.popup.is_open() {
+            self.popup.build(ui);
+        }
+
+        ui.set_item_default_focus();
+        if self.refocus {
+            ui.set_keyboard_focus_here(imgui::FocusedWidget::Previous);
+            self.refocus = false;
+        }
     }
 
     /*pub fn close(&mut self,) {
@@ -506,17 +1365,175 @@ impl ConsoleWindow {
     }*/
 
     pub fn run_cmd(&mut self, root: &mut dyn IVisitExt, cmd: String) {
-        let mut parts = cmd.split(' '); // TODO: shellesc
-        let cmd = parts.next().unwrap_or("");
-        let args = parts.collect::<Vec<_>>();
+        let cmd = match self.history.expand(&cmd) {
+            Ok(Some(expanded)) => {
+                self.write_colored([0.6, 0.6, 0.6, 1.], &format!("{}\n", expanded));
+                expanded
+            }
+            Ok(None) => cmd,
+            Err(e) => {
+                self.console.write_result(e.into());
+                return;
+            }
+        };
+        if let Err(e) = self.history.push(&cmd) {
+            self.writeln(ConsoleError::Custom(
+                format!("Failed to save history: {}", e).into(),
+            ));
+        }
+        self.run_line(root, &cmd);
+    }
 
+    /// Run a line without adding it to the history, recording its changes for undo.
+    fn run_line(&mut self, root: &mut dyn IVisitExt, line: &str) {
+        let history = &mut self.history;
+        let presets = &mut self.presets;
+        let undo = &mut self.undo;
+        let settings = &mut self.settings;
+        let log = settings.log.clone();
         let mut root = VisitMutExt(|f, console| {
-            root.visit_mut_ext(f, console);
-        });
+            visit_window(history, presets, undo, settings, root, f, console)
+        })
+        .with_ignore_case(self.ignore_case)
+        .with_origins(std::mem::take(&mut self.origins))
+        .with_transaction(self.transaction.take())
+        .with_log(log);
+
+        root.run_line(line, &mut self.console);
+        self.transaction = root.take_transaction();
+        let changes = root.take_changes();
+        self.origins = root.into_origins();
+        self.undo.push(changes);
+    }
+}
+
+/// Window state exposed as properties by `visit_window`
+struct Settings {
+    log: Option<LogQueue>,
+    scrollback: Scrollback,
+}
+
+/// Most lines of output to keep, 0 keeps every line
+struct Scrollback {
+    lines: usize,
+    default: usize,
+}
+
+impl cvar::IVisit for Scrollback {
+    fn visit_mut(&mut self, f: &mut dyn FnMut(&mut dyn cvar::INode)) {
+        f(&mut cvar::Property(
+            "scrollback",
+            "Most lines of output to keep, the oldest ones are dropped first. 0 keeps every line",
+            &mut self.lines,
+            self.default,
+        ));
+    }
+}
+
+/// Visit the builtins that need state from the window, followed by everything in `root`.
+fn visit_window(
+    history: &mut History,
+    presets: &mut Presets,
+    undo: &mut UndoStack,
+    settings: &mut Settings,
+    root: &mut dyn IVisitExt,
+    f: &mut dyn FnMut(&mut dyn cvar::INode),
+    console: &mut dyn IConsoleExt,
+) {
+    f(&mut cvar::Action(
+        "history",
+        "[clear]\nList previous commands, use !n to run one again",
+        |args, _| history.cmd_history(args, console),
+    ));
+    f(&mut cvar::Action(
+        "preset",
+        "save|load|delete <name>, list, diff <a> <b>\nKeep named snapshots of every property",
+        |args, _| presets.cmd_preset(args, root, console),
+    ));
+    // Changes to the window settings are recorded too, so undo sets them as well
+    f(&mut cvar::Action(
+        "undo",
+        "Revert the last property changes made from the console",
+        |_, _| {
+            let mut root = VisitMutExt(|f, console| {
+                visit_settings(settings, f);
+                root.visit_mut_ext(f, console);
+            });
+            undo.cmd_undo(&mut root, console)
+        },
+    ));
+    f(&mut cvar::Action(
+        "redo",
+        "Apply property changes again after undo",
+        |_, _| {
+            let mut root = VisitMutExt(|f, console| {
+                visit_settings(settings, f);
+                root.visit_mut_ext(f, console);
+            });
+            undo.cmd_redo(&mut root, console)
+        },
+    ));
+    visit_settings(settings, f);
+    root.visit_mut_ext(f, console);
+}
 
-        let result = root.exec(cmd, args);
-        self.console.write_result(result);
-        self.console.buf.append(&mut root.console.buf);
+/// Visit the window settings, as the `console` and `log` lists.
+fn visit_settings(settings: &mut Settings, f: &mut dyn FnMut(&mut dyn cvar::INode)) {
+    f(&mut cvar::List(
+        "console",
+        "Console window settings",
+        &mut settings.scrollback,
+    ));
+    if let Some(log) = &mut settings.log {
+        f(&mut cvar::List("log", "Records shown in the console", log));
+    }
+}
+
+/// Note for a property that was not set, because a higher layer than the one setting it did.
+fn kept_note(path: &str, origins: &Origins) -> String {
+    match origins.get(path) {
+        Some(origin) => format!("{}: kept, set by {}\n", path, origin.source),
+        None => format!("{}: kept\n", path),
+    }
+}
+
+/// Whether the builtin at `path` would set properties without staging them in a transaction
+fn writes_directly(path: &str, args: &[&str]) -> bool {
+    match path {
+        "reset" | "import" | "undo" | "redo" => true,
+        "preset" => args.first() == Some(&"load"),
+        _ => false,
+    }
+}
+
+/// Most scripts `exec` may run from within each other, so one that runs itself stops
+const MAX_EXEC_DEPTH: usize = 16;
+
+/// Command setting `path` to `value`, quoted so it can be run again with `exec`.
+fn script_line(path: &str, value: &str) -> String {
+    format!("{} {}\n", quote(path), quote(value))
+}
+
+/// Key index of an ASCII letter.
+///
+/// imgui only maps a handful of letters, but the common backends (winit, SDL, GLFW) all number
+/// letters contiguously, so the rest can be found relative to `A`.
+fn letter_key(ui: &imgui::Ui, letter: char) -> u32 {
+    ui.key_index(imgui::Key::A) + (letter as u32 - 'A' as u32)
+}
+
+/// Call `row` for each of the `count` rows that are visible in the current window, leaving the
+/// cursor after the last row as if all of them were drawn.
+fn clip_rows<F: FnMut(usize)>(count: usize, height: f32, mut row: F) {
+    let mut clipper = imgui::sys::ImGuiListClipper::default();
+    // SAFETY: `clipper` outlives every call using it, and `Step` ends it once it returns false
+    unsafe {
+        imgui::sys::ImGuiListClipper_Begin(&mut clipper, count as i32, height);
+        while imgui::sys::ImGuiListClipper_Step(&mut clipper) {
+            for i in clipper.DisplayStart..clipper.DisplayEnd {
+                row(i as usize);
+            }
+        }
     }
 }
 
@@ -541,30 +1558,284 @@ pub trait IVisitExt {
 pub struct VisitMutExt<F: FnMut(&mut dyn FnMut(&mut dyn cvar::INode), &mut dyn IConsoleExt)> {
     closure: F,
     console: ColoredConsole,
+    ignore_case: bool,
+    origins: Origins,
+    /// Layer and source recorded for properties set by `run_line`
+    layer: Layer,
+    source: String,
+    /// Properties changed by `run_line` so far
+    changes: Vec<Change>,
+    /// Properties `run_line` did not set, because a higher layer set them
+    kept: Vec<String>,
+    /// Values staged since `begin`, if a transaction is open
+    transaction: Option<Transaction>,
+    /// Scripts currently being run by `exec`
+    exec_depth: usize,
+    /// Set by `exec` when a line of its script failed, so `run_line` fails as well
+    script_failed: bool,
+    /// Log whose `log.filter.<target>` properties are added when a command names them
+    log: Option<LogQueue>,
 }
 
 impl<F: FnMut(&mut dyn FnMut(&mut dyn cvar::INode), &mut dyn IConsoleExt)> VisitMutExt<F> {
     pub fn new(closure: F) -> Self {
         VisitMutExt {
             closure,
-            console: ColoredConsole { buf: vec![] },
+            console: ColoredConsole::default(),
+            ignore_case: false,
+            origins: Origins::default(),
+            layer: Layer::Console,
+            source: "console".to_string(),
+            changes: vec![],
+            kept: vec![],
+            transaction: None,
+            exec_depth: 0,
+            script_failed: false,
+            log: None,
+        }
+    }
+
+    /// Run commands through `IgnoreCase`, matching names regardless of case.
+    pub fn with_ignore_case(mut self, ignore_case: bool) -> Self {
+        self.ignore_case = ignore_case;
+        self
+    }
+
+    /// Keep track of where properties were set from in `origins`, get them back with
+    /// `into_origins`.
+    pub fn with_origins(mut self, origins: Origins) -> Self {
+        self.origins = origins;
+        self
+    }
+
+    /// Record properties set by `run_line` as coming from `source` in `layer`. Defaults to the
+    /// console.
+    pub fn with_layer(mut self, layer: Layer, source: &str) -> Self {
+        self.layer = layer;
+        self.source = source.to_string();
+        self
+    }
+
+    /// Continue a transaction opened by `begin` in an earlier `VisitMutExt`, get it back with
+    /// `take_transaction`.
+    pub fn with_transaction(mut self, transaction: Option<Transaction>) -> Self {
+        self.transaction = transaction;
+        self
+    }
+
+    /// Accept `log.filter.<target>` for targets of `log` that haven't logged anything yet.
+    pub(crate) fn with_log(mut self, log: Option<LogQueue>) -> Self {
+        self.log = log;
+        self
+    }
+
+    /// Add the filter `name` refers to if it is an unknown `log.filter.<target>`, so it can be set.
+    fn add_log_filter(&mut self, name: &str) {
+        const PREFIX: &str = "log.filter.";
+        let named = name.get(..PREFIX.len()).is_some_and(|prefix| {
+            prefix == PREFIX || self.ignore_case && prefix.eq_ignore_ascii_case(PREFIX)
+        });
+        if !named || self.log.is_none() {
+            return;
+        }
+        let path = self.resolve(name).unwrap_or_else(|_| name.to_string());
+        if let (CmdType::NotFound, Some(log)) = (self.cmdtype(&path), &self.log) {
+            log.add_target(&name[PREFIX.len()..]);
+        }
+    }
+
+    /// The open transaction, if `begin` was run without a `commit` or `rollback` after it.
+    pub fn take_transaction(&mut self) -> Option<Transaction> {
+        self.transaction.take()
+    }
+
+    pub fn into_origins(self) -> Origins {
+        self.origins
+    }
+
+    /// Properties changed by `run_line` since the last call, through setting or resetting them.
+    pub fn take_changes(&mut self) -> Vec<Change> {
+        std::mem::take(&mut self.changes)
+    }
+
+    /// Current value of every property, by path
+    fn values(&mut self) -> BTreeMap<String, String> {
+        let mut values = BTreeMap::new();
+        cvar::console::walk(self, |path, node| {
+            if let cvar::NodeMut::Prop(prop) = node.as_node_mut() {
+                values.insert(path.to_string(), prop.get());
+            }
+        });
+        values
+    }
+
+    /// Record every property that differs from the `values` taken `before`.
+    ///
+    /// Properties are matched by path, as actions and other threads may add some in between.
+    fn record_changes(&mut self, before: BTreeMap<String, String>) {
+        for (path, new) in self.values() {
+            if let Some(old) = before.get(&path) {
+                if *old != new {
+                    let old = old.clone();
+                    self.changes.push(Change { path, old, new });
+                }
+            }
+        }
+    }
+
+    /// The path `var` refers to, taking `with_ignore_case` into account.
+    fn resolve(&mut self, var: &str) -> Result<String, ConsoleError> {
+        if self.ignore_case {
+            IgnoreCase(self).resolve(var)
+        } else {
+            Ok(var.to_string())
+        }
+    }
+
+    /// Parse and execute a full line of input, writing all results to `console`.
+    ///
+    /// Commands may be chained with `;`, `&&` and `||`, see `parse`.
+    /// Returns whether the last command that ran succeeded.
+    pub fn run_line(&mut self, line: &str, console: &mut dyn IConsoleExt) -> bool {
+        self.run_commands(line, console).0
+    }
+
+    /// `run_line`, also returning whether every command that ran succeeded.
+    fn run_commands(&mut self, line: &str, console: &mut dyn IConsoleExt) -> (bool, bool) {
+        let commands = match parse(line) {
+            Ok(commands) => commands,
+            Err(e) => {
+                console.write_result(e.into());
+                return (false, false);
+            }
+        };
+
+        let mut ok = true;
+        let mut all_ok = true;
+        for command in commands {
+            if !command.chain.should_run(ok) {
+                continue;
+            }
+
+            let mut parts = command.words.iter().map(String::as_str);
+            let cmd = parts.next().unwrap_or("");
+            let args = parts.collect::<Vec<_>>();
+
+            self.add_log_filter(cmd);
+            let path = self.resolve(cmd).unwrap_or_else(|_| cmd.to_string());
+            let setting = !args.is_empty() && matches!(self.cmdtype(&path), CmdType::Prop);
+            if setting && !self.origins.allows(&path, self.layer) {
+                console.write_colored([0.6, 0.6, 0.6, 1.], &kept_note(&path, &self.origins));
+                self.kept.push(path);
+                continue;
+            }
+            if setting && self.transaction.is_some() {
+                let result = self.stage(&path, args[0]);
+                ok = result.is_ok();
+                all_ok &= ok;
+                console.write_result(result);
+                continue;
+            }
+            if self.transaction.is_some() && !setting && writes_directly(&path, &args) {
+                let e = format!(
+                    "{} can't run until the transaction is committed or rolled back",
+                    cmd
+                );
+                console.write_result(ConsoleError::Custom(e.into()).into());
+                ok = false;
+                all_ok = false;
+                continue;
+            }
+            let old = if setting {
+                self.get(&path).0.ok()
+            } else {
+                None
+            };
+
+            let result = if self.ignore_case {
+                IgnoreCase(self).exec(cmd, args)
+            } else {
+                self.exec(cmd, args)
+            };
+            // Actions report failures by writing an error
+            ok = result.is_ok()
+                && !self.console.failed()
+                && !std::mem::take(&mut self.script_failed);
+            all_ok &= ok;
+            for change in std::mem::take(&mut self.console.changes) {
+                self.origins.set(&change.path, self.layer, &self.source);
+                self.changes.push(change);
+            }
+            if setting && ok {
+                self.origins.set(&path, self.layer, &self.source);
+                if let (Some(old), Ok(new)) = (old, self.get(&path).0) {
+                    if old != new {
+                        self.changes.push(Change { path, old, new });
+                    }
+                }
+            }
+            let unknown = matches!(
+                *result,
+                Err(ConsoleError::UnknownCommand) | Err(ConsoleError::UnknownProperty)
+            );
+            console.write_result(result);
+            if unknown {
+                self.write_suggestions(cmd, console);
+            }
+            for span in self.console.take_spans() {
+                console.write_colored(span.color, &span.text);
+            }
+        }
+        (ok, all_ok)
+    }
+
+    /// Write the paths closest to a `name` that was not found, if any.
+    pub fn write_suggestions(&mut self, name: &str, console: &mut dyn IConsoleExt) {
+        let close = self.suggest(name);
+        if !close.is_empty() {
+            console.write_colored(
+                [0.6, 0.6, 0.6, 1.],
+                &format!("Did you mean: {}?\n", close.join(", ")),
+            );
         }
     }
 
     pub fn cmd_help(&mut self, args: &[&str], console: &mut dyn IConsoleExt) {
         let out = {
-            if let Some(var) = args.get(0) {
-                self.help(var)
+            if let Some(var) = args.first() {
+                match self.resolve(var) {
+                    Ok(var) => self.describe(&var),
+                    Err(e) => e.into(),
+                }
             } else {
                 self.find(&|_| true)
             }
         };
+        let unknown = matches!(*out, Err(ConsoleError::UnknownProperty));
         console.write_result(out);
+        if let (true, Some(var)) = (unknown, args.first()) {
+            self.write_suggestions(var, console);
+        }
+    }
+
+    /// `CvarExt::help`, also showing where the value was set from.
+    fn describe(&mut self, var: &str) -> ConsoleResult {
+        let origin = self.origins.get(var).cloned();
+        let mut out = String::new();
+        cvar::console::find(self, var, |node| {
+            node.details(var, origin.as_ref(), &mut out);
+        });
+
+        if !out.is_empty() {
+            out.into()
+        } else {
+            ConsoleError::UnknownProperty.into()
+        }
     }
 
     pub fn cmd_find(&mut self, args: &[&str], console: &mut dyn IConsoleExt) {
         let out = {
-            if let Some(var) = args.get(0) {
+            if let Some(var) = args.first() {
                 self.find(&|path: &str| path.contains(var) && path != "find")
             } else {
                 ConsoleError::InvalidUsage("find <name>".to_string()).into()
@@ -574,15 +1845,251 @@ impl<F: FnMut(&mut dyn FnMut(&mut dyn cvar::INode), &mut dyn IConsoleExt)> Visit
     }
 
     pub fn cmd_reset(&mut self, args: &[&str], console: &mut dyn IConsoleExt) {
+        let before = self.values();
         let out = {
-            if let Some(var) = args.get(0) {
-                self.reset(var)
+            if let Some(var) = args.first() {
+                let out = if self.ignore_case {
+                    IgnoreCase(self).reset(var)
+                } else {
+                    self.reset(var)
+                };
+                if let (true, Ok(path)) = (out.is_ok(), self.resolve(var)) {
+                    self.origins.remove(&path);
+                }
+                out
             } else {
+                self.origins.clear();
                 self.reset_all()
             }
         };
+        self.record_changes(before);
+        let unknown = matches!(*out, Err(ConsoleError::UnknownProperty));
+        console.write_result(out);
+        if let (true, Some(var)) = (unknown, args.first()) {
+            self.write_suggestions(var, console);
+        }
+    }
+
+    /// Stage `value` to be set on `path` by `commit`.
+    ///
+    /// Nothing is written until then, so only values missing from the property's `values` are
+    /// rejected here.
+    fn stage(&mut self, path: &str, value: &str) -> ConsoleResult {
+        let mut found = None;
+        cvar::console::find(self, path, |node| {
+            if let cvar::NodeMut::Prop(prop) = node.as_node_mut() {
+                let invalid = prop.values().filter(|values| !values.contains(&value));
+                found = Some(invalid.map(|values| values.join(", ")));
+            }
+        });
+        match found {
+            None => return ConsoleError::UnknownProperty.into(),
+            Some(Some(values)) => {
+                return ConsoleError::InvalidValue(format!("expected one of {}", values)).into()
+            }
+            Some(None) => {}
+        }
+        if let Some(transaction) = &mut self.transaction {
+            transaction.stage(path, value);
+        }
+        format!("{} staged, {} on commit", path, value).into()
+    }
+
+    pub fn cmd_begin(&mut self, console: &mut dyn IConsoleExt) {
+        let out = if self.transaction.is_some() {
+            ConsoleError::Custom("A transaction is already open, commit or rollback first".into())
+                .into()
+        } else {
+            self.transaction = Some(Transaction::default());
+            "Staging property changes until commit or rollback".into()
+        };
+        console.write_result(out);
+    }
+
+    /// Set every staged value, as a single step for `undo`.
+    pub fn cmd_commit(&mut self, console: &mut dyn IConsoleExt) {
+        let transaction = match self.transaction.take() {
+            Some(transaction) => transaction,
+            None => {
+                console.write_result(ConsoleError::Custom("No transaction is open".into()).into());
+                return;
+            }
+        };
+
+        let count = transaction.len();
+        let mut written = vec![];
+        let mut failed = None;
+        for (path, value) in transaction.into_staged() {
+            let result = self.get(&path).0.and_then(|old| {
+                self.set(&path, &value).0?;
+                Ok(old)
+            });
+            match result {
+                Ok(old) => written.push((path, old)),
+                Err(e) => {
+                    failed = Some((path, e));
+                    break;
+                }
+            }
+        }
+
+        if let Some((path, e)) = failed {
+            // Values are only checked as they are set, so put back the ones set before this one
+            for (path, old) in written.into_iter().rev() {
+                if let Err(e) = self.set(&path, &old).0 {
+                    console.write_colored([0.6, 0.6, 0.6, 1.], &format!("{}: ", path));
+                    console.write_result(
+                        ConsoleError::Custom(format!("Failed to put back {}: {}", old, e).into())
+                            .into(),
+                    );
+                }
+            }
+            console.write_colored([0.6, 0.6, 0.6, 1.], &format!("{}: ", path));
+            console.write_result(Err(e).into());
+            console.write_result(
+                ConsoleError::Custom(
+                    format!("Nothing was committed, {} staged changes discarded", count).into(),
+                )
+                .into(),
+            );
+            return;
+        }
+
+        for (path, old) in written {
+            self.origins.set(&path, self.layer, &self.source);
+            if let Ok(new) = self.get(&path).0 {
+                if old != new {
+                    self.changes.push(Change { path, old, new });
+                }
+            }
+        }
+        console.write_result(format!("Committed {} staged changes", count).into());
+    }
+
+    pub fn cmd_rollback(&mut self, console: &mut dyn IConsoleExt) {
+        let out = match self.transaction.take() {
+            Some(transaction) => format!("Discarded {} staged changes", transaction.len()).into(),
+            None => ConsoleError::Custom("No transaction is open".into()).into(),
+        };
+        console.write_result(out);
+    }
+
+    pub fn cmd_writeconfig(&mut self, args: &[&str], console: &mut dyn IConsoleExt) {
+        let out = match args {
+            [file] => {
+                let mut contents = String::new();
+                let mut count = 0;
+                cvar::console::walk(self, |path, node| {
+                    if let cvar::NodeMut::Prop(prop) = node.as_node_mut() {
+                        contents.push_str(&script_line(path, &prop.get()));
+                        count += 1;
+                    }
+                });
+                match std::fs::write(file, contents) {
+                    Ok(()) => format!("Wrote {} properties to {}", count, file).into(),
+                    Err(e) => ConsoleError::Custom(format!("{}: {}", file, e).into()).into(),
+                }
+            }
+            _ => ConsoleError::InvalidUsage("writeconfig <file>".to_string()).into(),
+        };
         console.write_result(out);
     }
+
+    pub fn cmd_changed(&mut self, args: &[&str], console: &mut dyn IConsoleExt) {
+        let script = match args {
+            [] => false,
+            ["script"] => true,
+            _ => {
+                console.write_result(
+                    ConsoleError::InvalidUsage("changed [script]".to_string()).into(),
+                );
+                return;
+            }
+        };
+
+        let mut out = String::new();
+        cvar::console::walk(self, |path, node| {
+            if let cvar::NodeMut::Prop(prop) = node.as_node_mut() {
+                let (value, default) = (prop.get(), prop.default());
+                if value != default && script {
+                    out.push_str(&script_line(path, &value));
+                } else if value != default {
+                    out.push_str(&format!("{}: {} (Default: {})\n", path, value, default));
+                }
+            }
+        });
+
+        if out.is_empty() {
+            out.push_str("Every property has its default value");
+        }
+        console.write_result(out.into());
+    }
+
+    pub fn cmd_exec(&mut self, args: &[&str], console: &mut dyn IConsoleExt) {
+        let file = match args {
+            [file] => file,
+            _ => {
+                console.write_result(ConsoleError::InvalidUsage("exec <file>".to_string()).into());
+                return;
+            }
+        };
+        if self.exec_depth >= MAX_EXEC_DEPTH {
+            console.write_result(ConsoleError::Custom("exec nested too deeply".into()).into());
+            return;
+        }
+        match std::fs::read_to_string(file) {
+            Ok(script) => {
+                self.exec_depth += 1;
+                self.script_failed = !self.run_script(file, &script, console);
+                self.exec_depth -= 1;
+            }
+            Err(e) => {
+                console.write_result(ConsoleError::Custom(format!("{}: {}", file, e).into()).into())
+            }
+        }
+    }
+
+    /// Run each line of `script` with `run_line`, prefixing any output with `name` and the line
+    /// number. Blank lines and comments starting with `//` or `#` are skipped.
+    ///
+    /// Returns whether every line succeeded.
+    pub fn run_script(&mut self, name: &str, script: &str, console: &mut dyn IConsoleExt) -> bool {
+        self.run_lines(name, script, console, false)
+    }
+
+    /// `run_script`, optionally only showing the output of lines that failed.
+    fn run_lines(
+        &mut self,
+        name: &str,
+        script: &str,
+        console: &mut dyn IConsoleExt,
+        errors_only: bool,
+    ) -> bool {
+        let mut ok = true;
+        for (i, line) in script.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with("//") || line.starts_with('#') {
+                continue;
+            }
+
+            let mut out = ColoredConsole::default();
+            let source = std::mem::replace(&mut self.source, format!("{}:{}", name, i + 1));
+            // Earlier commands chained with `;` may have failed even if the last one succeeded
+            let (_, line_ok) = self.run_commands(line, &mut out);
+            self.source = source;
+            ok &= line_ok;
+            if errors_only && line_ok {
+                continue;
+            }
+            if !out.is_empty() {
+                console.write_colored([0.6, 0.6, 0.6, 1.], &format!("{}:{}: ", name, i + 1));
+                for span in out.take_spans() {
+                    console.write_colored(span.color, &span.text);
+                }
+            }
+        }
+        ok
+    }
 }
 
 impl<F: FnMut(&mut dyn FnMut(&mut dyn cvar::INode), &mut dyn IConsoleExt)> IVisitExt
@@ -601,7 +2108,7 @@ impl<F: FnMut(&mut dyn FnMut(&mut dyn cvar::INode), &mut dyn IConsoleExt)> cvar:
     for VisitMutExt<F>
 {
     fn visit_mut(&mut self, f: &mut dyn FnMut(&mut dyn cvar::INode)) {
-        let mut console = ColoredConsole { buf: vec![] };
+        let mut console = ColoredConsole::default();
         f(&mut cvar::Action(
             "help",
             "List all commands and properties",
@@ -617,6 +2124,56 @@ impl<F: FnMut(&mut dyn FnMut(&mut dyn cvar::INode), &mut dyn IConsoleExt)> cvar:
             "<var>\nSet a property to its default",
             |args, _| self.cmd_reset(args, &mut console),
         ));
+        f(&mut cvar::Action(
+            "writeconfig",
+            "<file>\nSave every property to a file, load it again with exec",
+            |args, _| self.cmd_writeconfig(args, &mut console),
+        ));
+        f(&mut cvar::Action(
+            "changed",
+            "[script]\nList properties that differ from their defaults, or the commands to set them",
+            |args, _| self.cmd_changed(args, &mut console),
+        ));
+        f(&mut cvar::Action(
+            "exec",
+            "<file>\nRun each line of a file as a command",
+            |args, _| self.cmd_exec(args, &mut console),
+        ));
+        f(&mut cvar::Action(
+            "begin",
+            "Stage property changes instead of setting them, until commit or rollback",
+            |_, _| self.cmd_begin(&mut console),
+        ));
+        f(&mut cvar::Action(
+            "commit",
+            "Set every property staged since begin",
+            |_, _| self.cmd_commit(&mut console),
+        ));
+        f(&mut cvar::Action(
+            "rollback",
+            "Discard every property staged since begin",
+            |_, _| self.cmd_rollback(&mut console),
+        ));
+        #[cfg(any(
+            feature = "format-json",
+            feature = "format-ron",
+            feature = "format-toml"
+        ))]
+        f(&mut cvar::Action(
+            "export",
+            "<file>\nSave every property to a .json, .ron or .toml file, nested by path",
+            |args, _| self.cmd_export(args, &mut console),
+        ));
+        #[cfg(any(
+            feature = "format-json",
+            feature = "format-ron",
+            feature = "format-toml"
+        ))]
+        f(&mut cvar::Action(
+            "import",
+            "<file>\nSet the properties in a .json, .ron or .toml file written by export",
+            |args, _| self.cmd_import(args, &mut console),
+        ));
         self.visit_mut_ext(f, &mut console);
         self.console = console
     }
@@ -633,10 +2190,441 @@ pub fn VisitMutExt<F: FnMut(&mut dyn FnMut(&mut dyn cvar::INode), &mut dyn ICons
     VisitMutExt::new(f)
 }
 
-/// Create a new standalone console window.
+/// Create a new standalone console window, running the `autoexec` script if given.
 /// Use `create_system` instead if you want amethyst integration.
 ///
 /// Be sure to call `build` on the returned window during your rendering stage
-pub fn create_console() -> ConsoleWindow {
-    ConsoleWindow::new()
+pub fn create_console(autoexec: Option<PathBuf>) -> ConsoleWindow {
+    match autoexec {
+        Some(path) => ConsoleWindow::new().with_autoexec(path),
+        None => ConsoleWindow::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct Config {
+        s: String,
+        n: i32,
+    }
+
+    impl IVisitExt for Config {
+        fn visit_mut_ext(
+            &mut self,
+            f: &mut dyn FnMut(&mut dyn cvar::INode),
+            console: &mut dyn IConsoleExt,
+        ) {
+            f(&mut cvar::Property("s", "", &mut self.s, String::new()));
+            f(&mut cvar::Property("n", "", &mut self.n, 0));
+            f(&mut cvar::Action("pass", "", |_, _| {
+                console.write("done\n")
+            }));
+            f(&mut cvar::Action("fail", "", |_, _| {
+                console.write_result(ConsoleError::InvalidUsage("fail".to_string()).into())
+            }));
+            f(&mut cvar::Action("fail_cvar", "", |_, out| {
+                out.write_error(&ConsoleError::InvalidUsage("fail_cvar".to_string()))
+            }));
+        }
+    }
+
+    /// Run `line` on `config`, returning whether it succeeded and its output
+    fn run(config: &mut Config, line: &str) -> (bool, String) {
+        let mut root = VisitMutExt(|f, console| config.visit_mut_ext(f, console));
+        let mut out = ColoredConsole::default();
+        let ok = root.run_line(line, &mut out);
+        (ok, out.text())
+    }
+
+    #[test]
+    fn edit_distances() {
+        assert_eq!(edit_distance("width", "width"), 0);
+        assert_eq!(edit_distance("HELP", "help"), 0);
+        assert_eq!(edit_distance("widht", "width"), 1);
+        assert_eq!(edit_distance("wdth", "width"), 1);
+        assert_eq!(edit_distance("widths", "width"), 1);
+        assert_eq!(edit_distance("wodth", "width"), 1);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+
+    #[test]
+    fn suggest_close_paths() {
+        let mut config = Config::default();
+        let mut root = VisitMutExt(|f, console| config.visit_mut_ext(f, console));
+        assert_eq!(root.suggest("pas"), ["pass"]);
+        assert_eq!(root.suggest("fial"), ["fail", "find"]);
+        assert!(root.suggest("nothing").is_empty());
+    }
+
+    #[test]
+    fn chain_after_actions() {
+        let mut config = Config::default();
+        assert_eq!(run(&mut config, "pass && s a"), (true, "done".to_string()));
+        assert_eq!(config.s, "a");
+
+        let (ok, out) = run(&mut config, "fail && s b");
+        assert!(!ok);
+        assert_eq!(out, "Usage: fail");
+        assert_eq!(config.s, "a");
+
+        let (ok, out) = run(&mut config, "fail_cvar && s b || n 2");
+        assert!(ok);
+        assert_eq!(out, "Usage: fail_cvar");
+        assert_eq!((config.s.as_str(), config.n), ("a", 2));
+
+        assert!(run(&mut config, "pass || n 3").0);
+        assert_eq!(config.n, 2);
+    }
+
+    #[test]
+    fn exec_recursion() {
+        let path = std::env::temp_dir().join(format!("exec_recursion_{}.cfg", std::process::id()));
+        let path = path.to_str().unwrap();
+        std::fs::write(path, format!("n 1\nexec {}\n", quote(path))).unwrap();
+        let mut config = Config::default();
+        let (_, out) = run(&mut config, &format!("exec {}", quote(path)));
+        std::fs::remove_file(path).unwrap();
+        assert!(out.ends_with("exec nested too deeply"), "{}", out);
+        assert_eq!(config.n, 1);
+    }
+
+    #[test]
+    fn script_errors_only() {
+        let path = std::env::temp_dir().join(format!("errors_only_{}.cfg", std::process::id()));
+        let path = path.to_str().unwrap();
+        std::fs::write(path, "n 2\nfail\n").unwrap();
+        let script = format!("pass\nfail\nfail; n 1\nexec {}\n", quote(path));
+        let mut config = Config::default();
+        let mut root = VisitMutExt(|f, console| config.visit_mut_ext(f, console));
+        let mut out = ColoredConsole::default();
+        let ok = root.run_lines("autoexec.cfg", &script, &mut out, true);
+        std::fs::remove_file(path).unwrap();
+        assert!(!ok);
+        assert_eq!(
+            out.text(),
+            format!(
+                "autoexec.cfg:2: Usage: fail\n\
+                 autoexec.cfg:3: Usage: fail\n\
+                 autoexec.cfg:4: {0}:2: Usage: fail",
+                path
+            )
+        );
+        assert_eq!(config.n, 2);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn env_not_unicode() {
+        use std::os::unix::ffi::OsStrExt;
+        std::env::set_var("ENV_NOT_UNICODE_N", "5");
+        std::env::set_var(
+            "ENV_NOT_UNICODE_S",
+            std::ffi::OsStr::from_bytes(&[b'a', 0xff]),
+        );
+        let mut config = Config::default();
+        let mut window = ConsoleWindow::new();
+        assert!(!window.apply_env_overrides(&mut config, "ENV_NOT_UNICODE_"));
+        assert_eq!(config.n, 5);
+        assert_eq!(
+            window.console.text(),
+            "environment: ENV_NOT_UNICODE_S is not valid UTF-8"
+        );
+    }
+
+    #[test]
+    fn keep_higher_layers() {
+        let mut config = Config::default();
+        let mut origins = Origins::default();
+        origins.set("n", Layer::Console, "console");
+        let mut root = VisitMutExt(|f, console| config.visit_mut_ext(f, console))
+            .with_origins(origins)
+            .with_layer(Layer::Config, "test.cfg");
+        let mut out = ColoredConsole::default();
+        assert!(root.run_line("n 5 && s a", &mut out));
+        assert_eq!(out.text(), "n: kept, set by console");
+        assert_eq!(root.kept, ["n"]);
+        assert_eq!(root.into_origins().get("s").unwrap().source, "test.cfg");
+        assert_eq!((config.n, config.s.as_str()), (0, "a"));
+    }
+
+    #[test]
+    fn watch_reload_summary() {
+        let path = std::env::temp_dir().join(format!("watch_{}.cfg", std::process::id()));
+        std::fs::write(&path, "n 1\ns a\n").unwrap();
+        let mut config = Config::default();
+        let mut window = ConsoleWindow::new();
+        window.watch = Some(Watcher::new(path.clone(), std::time::Duration::ZERO));
+        window.update(&mut config);
+        assert_eq!((config.n, config.s.as_str()), (1, "a"));
+        assert!(window.console.is_empty());
+
+        window.run_line(&mut config, "n 2");
+        std::fs::write(&path, "n 3\ns b\n").unwrap();
+        window.update(&mut config);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!((config.n, config.s.as_str()), (2, "b"));
+        assert_eq!(
+            window.console.text(),
+            format!(
+                "Reloaded {}, 1 properties changed, 1 kept\ns: a -> b\nn: kept, set by console",
+                path.display()
+            )
+        );
+    }
+
+    /// Run `line` in `window`, returning only its output
+    fn run_window(window: &mut ConsoleWindow, config: &mut Config, line: &str) -> String {
+        window.clear();
+        window.run_line(config, line);
+        window.console.text()
+    }
+
+    #[test]
+    fn preset_load_is_tracked() {
+        let mut config = Config::default();
+        let mut window = ConsoleWindow::new();
+        run_window(&mut window, &mut config, "n 7; preset save seven; reset n");
+        let overrides = [("n".to_string(), "5".to_string())];
+        window.apply_overrides(&mut config, Layer::Config, "autoexec.cfg:12", &overrides);
+        run_window(&mut window, &mut config, "preset load seven");
+        assert_eq!(config.n, 7);
+        assert!(run_window(&mut window, &mut config, "help n").contains("set by console"));
+        run_window(&mut window, &mut config, "undo");
+        assert_eq!(config.n, 5);
+    }
+
+    #[test]
+    fn undo_and_redo() {
+        let mut config = Config::default();
+        let mut window = ConsoleWindow::new();
+        run_window(&mut window, &mut config, "n 1");
+        run_window(&mut window, &mut config, "n 2; s a");
+        let out = run_window(&mut window, &mut config, "undo");
+        assert_eq!(out, "s: a -> \nn: 2 -> 1");
+        assert_eq!((config.n, config.s.as_str()), (1, ""));
+        run_window(&mut window, &mut config, "undo");
+        assert_eq!(config.n, 0);
+        assert!(run_window(&mut window, &mut config, "help n").contains("set by console"));
+        run_window(&mut window, &mut config, "redo; redo");
+        assert_eq!((config.n, config.s.as_str()), (2, "a"));
+        let out = run_window(&mut window, &mut config, "redo");
+        assert_eq!(out, "Nothing to redo");
+
+        run_window(&mut window, &mut config, "undo; n 3");
+        assert_eq!((config.n, config.s.as_str()), (3, ""));
+        run_window(&mut window, &mut config, "undo");
+        assert_eq!((config.n, config.s.as_str()), (1, ""));
+        assert_eq!(run_window(&mut window, &mut config, "redo"), "n: 1 -> 3");
+    }
+
+    #[test]
+    fn record_changes_by_path() {
+        let (mut a, mut n, mut grown) = (0, 0, false);
+        let mut root = VisitMutExt(|f, _| {
+            if grown {
+                f(&mut cvar::Property("a", "", &mut a, 1));
+            }
+            f(&mut cvar::Property("n", "", &mut n, 0));
+            f(&mut cvar::Action("grow", "", |_, _| grown = true));
+        });
+        let before = root.values();
+        root.set("n", "5");
+        root.exec("grow", vec![]);
+        root.record_changes(before);
+        assert_eq!(
+            root.take_changes(),
+            [Change {
+                path: "n".to_string(),
+                old: "0".to_string(),
+                new: "5".to_string()
+            }]
+        );
+    }
+
+    #[test]
+    fn log_filter_any_target() {
+        let mut config = Config::default();
+        let mut window = ConsoleWindow::new().with_log(LogQueue::default());
+        run_window(&mut window, &mut config, "log.filter.amethyst_rendy debug");
+        let out = run_window(&mut window, &mut config, "log.filter.amethyst_rendy");
+        assert_eq!(out, "debug");
+        let out = run_window(&mut window, &mut config, "log.filter.gfx loud");
+        assert!(out.starts_with("Invalid value"), "{}", out);
+
+        let path = std::env::temp_dir().join(format!("log_{}.cfg", std::process::id()));
+        let line = format!("writeconfig {}", path.display());
+        run_window(&mut window, &mut config, &line);
+        let script = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(script.contains("log.filter.amethyst_rendy debug\n"));
+
+        let mut window = ConsoleWindow::new().with_log(LogQueue::default());
+        let mut out = ColoredConsole::default();
+        window.run_config(&mut config, &path, &script, &mut out);
+        assert!(out.is_empty(), "{}", out.text());
+        let overrides = [("log.filter.gfx".to_string(), "warn".to_string())];
+        assert!(window.apply_overrides(&mut config, Layer::CommandLine, "+", &overrides));
+        let out = run_window(
+            &mut window,
+            &mut config,
+            "log.filter.amethyst_rendy; log.filter.gfx",
+        );
+        assert_eq!(out, "debug\nwarn");
+        let out = run_window(&mut window, &mut config, "log.filter.gfx.x");
+        assert_eq!(out, "default");
+    }
+
+    #[test]
+    fn undo_window_settings() {
+        let mut config = Config::default();
+        let mut window = ConsoleWindow::new().with_log(LogQueue::default());
+        run_window(
+            &mut window,
+            &mut config,
+            "console.scrollback 10; log.level warn",
+        );
+        let out = run_window(&mut window, &mut config, "undo");
+        assert_eq!(
+            out,
+            "log.level: warn -> info\nconsole.scrollback: 10 -> 1000"
+        );
+        assert_eq!(window.settings.scrollback.lines, 1000);
+    }
+
+    #[test]
+    fn truncate_lines() {
+        let mut console = ColoredConsole::default();
+        console.write("1\n2\n3");
+        console.truncate_lines(2);
+        assert_eq!(console.text(), "2\n3");
+        console.write("4\n");
+        assert_eq!(console.text(), "2\n34");
+        console.truncate_lines(0);
+        assert!(console.is_empty());
+        console.write("5");
+        assert_eq!(console.text(), "5");
+        assert_eq!(console.dropped(), 3);
+    }
+
+    #[test]
+    fn scrollback() {
+        let mut config = Config::default();
+        let mut window = ConsoleWindow::new().with_scrollback(3);
+        run_window(&mut window, &mut config, "n 1; n; n; n; n");
+        window.update(&mut config);
+        assert_eq!(window.console.text(), "1\n1\n1");
+        run_window(&mut window, &mut config, "console.scrollback 0; n; n; n; n");
+        window.update(&mut config);
+        assert_eq!(window.console.line_count(), 4);
+        run_window(&mut window, &mut config, "reset console.scrollback");
+        let out = run_window(&mut window, &mut config, "console.scrollback");
+        assert_eq!(out, "3");
+    }
+
+    #[test]
+    fn console_lines() {
+        let red = [1., 0., 0., 1.];
+        let mut console = ColoredConsole::default();
+        console.write("a\n\nb");
+        console.write_colored(red, "c\nd\n");
+        console.writeln("  ");
+        console.writeln("e  \n");
+        assert_eq!(console.text(), "a\n\nbc\nd\ne");
+        assert_eq!(console.line_count(), 5);
+        let colors = console.lines[2].iter().map(|span| span.color);
+        assert_eq!(colors.collect::<Vec<_>>(), [[1.; 4], red]);
+
+        // Taking the spans and writing them again gives the same lines
+        let mut copy = ColoredConsole::default();
+        for span in console.take_spans() {
+            copy.write(span);
+        }
+        assert!(console.is_empty());
+        assert_eq!(copy.text(), "a\n\nbc\nd\ne");
+        let colors = copy.lines[2].iter().map(|span| span.color);
+        assert_eq!(colors.collect::<Vec<_>>(), [[1.; 4], red]);
+
+        // Without a newline the last line stays open
+        copy.write("f");
+        let spans = copy.take_spans();
+        assert_eq!(spans.last().map(|span| &span.text[..]), Some("f"));
+        copy.write("g");
+        assert_eq!(copy.text(), "g");
+    }
+
+    #[test]
+    fn prepend() {
+        let mut console = ColoredConsole::default();
+        console.write("welcome\n> ");
+        let mut earlier = ColoredConsole::default();
+        earlier.write_result(ConsoleError::UnknownCommand.into());
+        console.prepend(earlier);
+        assert_eq!(console.text(), "Unknown command\nwelcome\n> ");
+        assert!(console.failed());
+        console.write("typed");
+        assert_eq!(console.text(), "Unknown command\nwelcome\n> typed");
+    }
+
+    #[test]
+    fn stage_without_writing() {
+        let (mut n, mut last, mut writes) = (0, 0, 0);
+        let mut root = VisitMutExt(|f, _| {
+            f(&mut cvar::Property("n", "", &mut n, 0));
+            if n != last {
+                writes += 1;
+                last = n;
+            }
+        });
+        let mut out = ColoredConsole::default();
+        assert!(root.run_line("begin; n 5; n 6", &mut out));
+        assert!(!root.run_line("missing 1", &mut out));
+        assert!(root.run_line("commit", &mut out));
+        drop(root);
+        assert_eq!((n, writes), (6, 1));
+    }
+
+    #[test]
+    fn commit_all_or_nothing() {
+        let mut config = Config::default();
+        let mut window = ConsoleWindow::new();
+        let out = run_window(&mut window, &mut config, "begin; s a; n x; commit");
+        assert!(out.ends_with("Nothing was committed, 2 staged changes discarded"));
+        assert_eq!((config.s.as_str(), config.n), ("", 0));
+        assert_eq!(
+            run_window(&mut window, &mut config, "undo"),
+            "Nothing to undo"
+        );
+        assert!(window.transaction.is_none());
+    }
+
+    #[test]
+    fn transaction_refuses_direct_writes() {
+        let mut config = Config::default();
+        let mut window = ConsoleWindow::new();
+        run_window(&mut window, &mut config, "n 1; begin; n 7");
+        let out = run_window(&mut window, &mut config, "reset n");
+        assert_eq!(
+            out,
+            "reset can't run until the transaction is committed or rolled back"
+        );
+        let out = run_window(&mut window, &mut config, "undo || preset load x");
+        assert!(out.ends_with("preset can't run until the transaction is committed or rolled back"));
+        assert_eq!(config.n, 1);
+        run_window(&mut window, &mut config, "commit");
+        assert_eq!(config.n, 7);
+    }
+
+    #[test]
+    fn chain_after_properties() {
+        let mut config = Config::default();
+        assert!(!run(&mut config, "n x && s a").0);
+        assert!(run(&mut config, "missing || s b").0);
+        assert_eq!(config.s, "b");
+        assert!(run(&mut config, "n 4 && s c").0);
+        assert_eq!((config.s.as_str(), config.n), ("c", 4));
+    }
 }
@@ -0,0 +1,384 @@
+use crate::TextSpan;
+use log::{Level, LevelFilter, Log, Metadata, Record, SetLoggerError};
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// Records kept while nothing drains the queue, older ones are dropped first
+const MAX_QUEUED: usize = 1000;
+
+/// State shared by every handle to a `LogQueue`
+struct Shared {
+    records: VecDeque<TextSpan>,
+    level: LevelFilter,
+    default_level: LevelFilter,
+    /// Level for records whose target starts with the key, or `None` to follow `level`
+    targets: BTreeMap<String, Option<LevelFilter>>,
+    /// Whether `log::max_level` follows the levels, see `LogQueue::own_max_level`
+    owns_max_level: bool,
+}
+
+impl Shared {
+    /// The level for records from `target`, set by its longest matching filter.
+    fn level_for(&self, target: &str) -> LevelFilter {
+        self.targets
+            .iter()
+            .filter(|(prefix, _)| {
+                target == prefix.as_str()
+                    || target.starts_with(prefix.as_str())
+                        && target[prefix.len()..].starts_with("::")
+            })
+            .filter_map(|(prefix, level)| level.map(|level| (prefix.len(), level)))
+            .max_by_key(|(len, _)| *len)
+            .map_or(self.level, |(_, level)| level)
+    }
+
+    /// The most verbose level of any target
+    fn max_level(&self) -> LevelFilter {
+        self.targets
+            .values()
+            .filter_map(|level| *level)
+            .fold(self.level, Ord::max)
+    }
+
+    fn apply_max_level(&self) {
+        if self.owns_max_level {
+            log::set_max_level(self.max_level());
+        }
+    }
+}
+
+/// Log records waiting to be shown, shared between a `ConsoleLogger` and a `ConsoleWindow`.
+///
+/// Cloning gives another handle to the same queue. Visiting it adds the `level` and
+/// `filter.<target>` properties, see `ConsoleWindow::with_log`.
+#[derive(Clone)]
+pub struct LogQueue(Arc<Mutex<Shared>>);
+
+impl Default for LogQueue {
+    fn default() -> Self {
+        LogQueue(Arc::new(Mutex::new(Shared {
+            records: VecDeque::new(),
+            level: LevelFilter::Info,
+            default_level: LevelFilter::Info,
+            targets: BTreeMap::new(),
+            owns_max_level: false,
+        })))
+    }
+}
+
+impl LogQueue {
+    fn lock(&self) -> MutexGuard<'_, Shared> {
+        // Nothing panics while holding the lock, but keep logging if something ever does
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Take every queued record, oldest first, colored by level.
+    pub fn drain(&self) -> Vec<TextSpan> {
+        self.lock().records.drain(..).collect()
+    }
+
+    /// Keep `log::max_level` at the most verbose level of `log.level` and the filters.
+    ///
+    /// `ConsoleLogger::init` does this already. Call it when the logger was chained into another
+    /// one instead, after that one was installed, or records more verbose than the level it set
+    /// are dropped before reaching the console. The other logger may still filter records itself.
+    pub fn own_max_level(&self) {
+        let mut shared = self.lock();
+        shared.owns_max_level = true;
+        shared.apply_max_level();
+    }
+
+    /// Add the `filter.<target>` property, so a target can be filtered before it logs anything.
+    pub(crate) fn add_target(&self, target: &str) {
+        if !target.is_empty() {
+            self.lock()
+                .targets
+                .entry(target.to_string())
+                .or_insert(None);
+        }
+    }
+}
+
+impl cvar::IVisit for LogQueue {
+    fn visit_mut(&mut self, f: &mut dyn FnMut(&mut dyn cvar::INode)) {
+        // Don't hold the lock while visiting, in case anything logs meanwhile
+        let (mut level, default, mut targets) = {
+            let shared = self.lock();
+            (
+                LevelProp(shared.level),
+                LevelProp(shared.default_level),
+                shared.targets.clone(),
+            )
+        };
+        f(&mut cvar::Property(
+            "level",
+            "Most verbose records to log: off, error, warn, info, debug or trace",
+            &mut level,
+            default,
+        ));
+        f(&mut cvar::List(
+            "filter",
+            "Level for the records of each target, any target can be set",
+            &mut TargetProps(&mut targets),
+        ));
+
+        let mut shared = self.lock();
+        shared.level = level.0;
+        shared.targets.extend(targets);
+        shared.apply_max_level();
+    }
+}
+
+/// The `filter.<target>` properties
+struct TargetProps<'a>(&'a mut BTreeMap<String, Option<LevelFilter>>);
+
+impl cvar::IVisit for TargetProps<'_> {
+    fn visit_mut(&mut self, f: &mut dyn FnMut(&mut dyn cvar::INode)) {
+        for (target, level) in self.0.iter_mut() {
+            let mut prop = TargetProp(*level);
+            f(&mut cvar::Property(
+                target,
+                "Most verbose records to log from this target, or default to follow log.level",
+                &mut prop,
+                TargetProp(None),
+            ));
+            *level = prop.0;
+        }
+    }
+}
+
+/// Error for a level that isn't one of `off`, `error`, `warn`, `info`, `debug` or `trace`
+#[derive(Debug)]
+struct ParseLevelError;
+
+impl fmt::Display for ParseLevelError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("expected off, error, warn, info, debug or trace")
+    }
+}
+
+impl std::error::Error for ParseLevelError {}
+
+/// `LevelFilter` as a property, `log::ParseLevelError` is only an `Error` with log's `std` feature
+#[derive(Clone, Copy, PartialEq)]
+struct LevelProp(LevelFilter);
+
+impl FromStr for LevelProp {
+    type Err = ParseLevelError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse().map(LevelProp).map_err(|_| ParseLevelError)
+    }
+}
+
+impl fmt::Display for LevelProp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0.to_string().to_lowercase())
+    }
+}
+
+/// A target's level, `default` follows `log.level`
+#[derive(Clone, Copy, PartialEq)]
+struct TargetProp(Option<LevelFilter>);
+
+impl FromStr for TargetProp {
+    type Err = ParseLevelError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("default") {
+            Ok(TargetProp(None))
+        } else {
+            s.parse().map(|LevelProp(level)| TargetProp(Some(level)))
+        }
+    }
+}
+
+impl fmt::Display for TargetProp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Some(level) => LevelProp(level).fmt(f),
+            None => f.write_str("default"),
+        }
+    }
+}
+
+/// A `log::Log` that queues records for the console, passing them on to another logger as well.
+///
+/// Install it with `init`, or chain it into another logger as a `Box<dyn Log>`, eg. with
+/// `fern::Dispatch::chain`, and call `LogQueue::own_max_level` once that one is installed. Amethyst's `start_logger` installs itself as the global logger, so
+/// it can't be combined with `init`.
+pub struct ConsoleLogger {
+    queue: LogQueue,
+    next: Option<Box<dyn Log>>,
+}
+
+impl ConsoleLogger {
+    pub fn new(queue: LogQueue) -> Self {
+        ConsoleLogger { queue, next: None }
+    }
+
+    /// Also pass every record to `next`, eg. one that prints to the terminal.
+    pub fn with_next(mut self, next: Box<dyn Log>) -> Self {
+        self.next = Some(next);
+        self
+    }
+
+    /// Only log records up to `level`, unless a target filter says otherwise. Defaults to `Info`.
+    pub fn with_level(self, level: LevelFilter) -> Self {
+        {
+            let mut shared = self.queue.lock();
+            shared.level = level;
+            shared.default_level = level;
+        }
+        self
+    }
+
+    /// Log records from `target` and its submodules up to `level` instead.
+    pub fn with_filter(self, target: &str, level: LevelFilter) -> Self {
+        self.queue
+            .lock()
+            .targets
+            .insert(target.to_string(), Some(level));
+        self
+    }
+
+    /// Install as the global logger. Fails if a logger was already installed.
+    pub fn init(self) -> Result<(), SetLoggerError> {
+        let queue = self.queue.clone();
+        log::set_logger(Box::leak(Box::new(self)))?;
+        let mut shared = queue.lock();
+        shared.owns_max_level = true;
+        shared.apply_max_level();
+        Ok(())
+    }
+}
+
+/// Console color for records of `level`
+fn level_color(level: Level) -> [f32; 4] {
+    match level {
+        Level::Error => [1., 0., 0., 1.],
+        Level::Warn => [1., 1., 0., 1.],
+        Level::Info => [1., 1., 1., 1.],
+        Level::Debug | Level::Trace => [0.6, 0.6, 0.6, 1.],
+    }
+}
+
+impl Log for ConsoleLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.queue.lock().level_for(metadata.target())
+    }
+
+    fn log(&self, record: &Record) {
+        {
+            let mut shared = self.queue.lock();
+            // Offer a filter for every crate and module that logs something
+            let target = record.target();
+            let krate = target.split("::").next().unwrap_or_default();
+            for name in &[krate, target] {
+                if !shared.targets.contains_key(*name) {
+                    shared.targets.insert(name.to_string(), None);
+                }
+            }
+            if record.level() > shared.level_for(record.target()) {
+                return;
+            }
+            if shared.records.len() >= MAX_QUEUED {
+                shared.records.pop_front();
+            }
+            shared.records.push_back(TextSpan {
+                color: level_color(record.level()),
+                text: format!(
+                    "[{}][{}] {}\n",
+                    record.level(),
+                    record.target(),
+                    record.args()
+                ),
+            });
+        }
+        if let Some(next) = &self.next {
+            next.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(next) = &self.next {
+            next.flush();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CvarExt;
+
+    #[test]
+    fn level_for_longest_prefix() {
+        let queue = LogQueue::default();
+        ConsoleLogger::new(queue.clone())
+            .with_level(LevelFilter::Warn)
+            .with_filter("gfx", LevelFilter::Debug)
+            .with_filter("gfx::device", LevelFilter::Off);
+        queue.add_target("gfx::device::gl");
+        let shared = queue.lock();
+        assert_eq!(shared.level_for("gfx"), LevelFilter::Debug);
+        assert_eq!(shared.level_for("gfx::texture"), LevelFilter::Debug);
+        assert_eq!(shared.level_for("gfx::device::gl"), LevelFilter::Off);
+        assert_eq!(shared.level_for("gfx_hal"), LevelFilter::Warn);
+        assert_eq!(shared.max_level(), LevelFilter::Debug);
+    }
+
+    #[test]
+    fn properties() {
+        let mut queue = LogQueue::default();
+        queue.add_target("gfx");
+        assert!(queue.set("filter.gfx", "TRACE").is_ok());
+        assert_eq!(queue.get("filter.gfx").0.unwrap(), "trace");
+        assert!(queue.set("filter.gfx", "loud").is_err());
+        assert!(queue.set("filter.gfx", "default").is_ok());
+        assert!(queue.set("level", "default").is_err());
+        assert!(queue.set("level", "off").is_ok());
+        assert_eq!(queue.lock().level_for("gfx"), LevelFilter::Off);
+        assert!(queue.get("filter.wgpu").is_err());
+    }
+
+    #[test]
+    fn own_max_level() {
+        let mut queue = LogQueue::default();
+        queue.set("level", "warn");
+        assert_eq!(log::max_level(), LevelFilter::Off);
+        queue.own_max_level();
+        assert_eq!(log::max_level(), LevelFilter::Warn);
+        queue.add_target("gfx");
+        queue.set("filter.gfx", "trace");
+        assert_eq!(log::max_level(), LevelFilter::Trace);
+    }
+
+    #[test]
+    fn records_are_filtered() {
+        let queue = LogQueue::default();
+        let logger = ConsoleLogger::new(queue.clone()).with_filter("gfx", LevelFilter::Error);
+        let record = |level, target| {
+            logger.log(
+                &Record::builder()
+                    .level(level)
+                    .target(target)
+                    .args(format_args!("hi"))
+                    .build(),
+            )
+        };
+        record(Level::Warn, "gfx::device");
+        record(Level::Warn, "game::ball");
+        record(Level::Debug, "game::ball");
+        let text = queue
+            .drain()
+            .into_iter()
+            .map(|span| span.text)
+            .collect::<String>();
+        assert_eq!(text, "[WARN][game::ball] hi\n");
+        let targets = queue.lock().targets.keys().cloned().collect::<Vec<_>>();
+        assert_eq!(targets, ["game", "game::ball", "gfx", "gfx::device"]);
+    }
+}
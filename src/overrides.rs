@@ -0,0 +1,222 @@
+use crate::{IVisitExt, VisitMutExt};
+use std::collections::HashMap;
+
+/// Find Quake style `+set name value` or `+name value` overrides in command line arguments.
+///
+/// Anything else is ignored, so these can be mixed with the program's own arguments. Pass
+/// `std::env::args_os().skip(1)` without the arguments that are not valid UTF-8, and apply the
+/// result with `ConsoleWindow::apply_overrides`.
+///
+/// `game +set width 120 +color red` gives `[("width", "120"), ("color", "red")]`.
+pub fn command_line_overrides<I, S>(args: I) -> Vec<(String, String)>
+where
+    I: IntoIterator<Item = S>,
+    S: Into<String>,
+{
+    let mut overrides = vec![];
+    let mut args = args.into_iter().map(Into::into);
+    while let Some(arg) = args.next() {
+        let name = match arg.strip_prefix('+') {
+            Some("set") => args.next(),
+            Some(name) if !name.is_empty() => Some(name.to_string()),
+            _ => continue,
+        };
+        if let (Some(name), Some(value)) = (name, args.next()) {
+            overrides.push((name, value));
+        }
+    }
+    overrides
+}
+
+/// Name of the environment variable that overrides the property at `path`, see `env_overrides`.
+///
+/// The path is upper cased and anything other than a letter or digit becomes `_`, so with the
+/// prefix `GAME_CVAR_`, `arena.width` is set by `GAME_CVAR_ARENA_WIDTH`.
+pub fn env_var_name(prefix: &str, path: &str) -> String {
+    let name = path
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>();
+    format!("{}{}", prefix, name)
+}
+
+/// Overrides found in environment variables by `env_overrides`
+#[derive(Debug, Default)]
+pub struct EnvOverrides {
+    /// `(path, value)` pairs, sorted by path
+    pub overrides: Vec<(String, String)>,
+    /// Variables that match no property
+    pub unknown: Vec<String>,
+    /// Variables that match more than one property, with their paths. These are left out of
+    /// `overrides`, as there is no telling which one was meant.
+    pub ambiguous: Vec<(String, Vec<String>)>,
+}
+
+/// Find overrides for the properties of `root` in environment variables starting with `prefix`.
+///
+/// Pass the valid UTF-8 entries of `std::env::vars_os()` as `vars`, and apply the result with
+/// `ConsoleWindow::apply_overrides`. Each property is matched by its `env_var_name`.
+pub fn env_overrides<I>(root: &mut dyn IVisitExt, prefix: &str, vars: I) -> EnvOverrides
+where
+    I: IntoIterator<Item = (String, String)>,
+{
+    let mut paths = HashMap::<_, Vec<_>>::new();
+    let mut root = VisitMutExt(|f, console| root.visit_mut_ext(f, console));
+    cvar::console::walk(&mut root, |path, node| {
+        if let cvar::NodeMut::Prop(_) = node.as_node_mut() {
+            paths
+                .entry(env_var_name(prefix, path))
+                .or_default()
+                .push(path.to_string());
+        }
+    });
+
+    let mut found = EnvOverrides::default();
+    for (name, value) in vars {
+        if !name.starts_with(prefix) {
+            continue;
+        }
+        match paths.get(&name).map(Vec::as_slice) {
+            Some([path]) => found.overrides.push((path.clone(), value)),
+            Some(clashing) => found.ambiguous.push((name, clashing.to_vec())),
+            None => found.unknown.push(name),
+        }
+    }
+    found.overrides.sort();
+    found.unknown.sort();
+    found.ambiguous.sort();
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn command_line() {
+        let args = [
+            "--fullscreen",
+            "+set",
+            "width",
+            "120",
+            "level1",
+            "+color",
+            "light blue",
+            "+",
+            "+set",
+            "height",
+        ];
+        assert_eq!(
+            command_line_overrides(args.iter().copied()),
+            [
+                ("width".to_string(), "120".to_string()),
+                ("color".to_string(), "light blue".to_string())
+            ]
+        );
+        assert!(command_line_overrides(vec!["+width".to_string()]).is_empty());
+    }
+
+    #[test]
+    fn env_var_names() {
+        assert_eq!(env_var_name("GAME_", "width"), "GAME_WIDTH");
+        assert_eq!(
+            env_var_name("GAME_", "arena.max-width2"),
+            "GAME_ARENA_MAX_WIDTH2"
+        );
+        assert_eq!(env_var_name("", "é"), "_");
+    }
+
+    struct Config {
+        width: i32,
+        arena_width: i32,
+    }
+
+    impl IVisitExt for Config {
+        fn visit_mut_ext(
+            &mut self,
+            f: &mut dyn FnMut(&mut dyn cvar::INode),
+            _: &mut dyn crate::IConsoleExt,
+        ) {
+            f(&mut cvar::Property("width", "", &mut self.width, 0));
+            f(&mut cvar::Property(
+                "arena.width",
+                "",
+                &mut self.arena_width,
+                0,
+            ));
+        }
+    }
+
+    #[test]
+    fn env() {
+        let mut config = Config {
+            width: 0,
+            arena_width: 0,
+        };
+        let vars = [
+            ("PATH", "/bin"),
+            ("GAME_WIDTH", "1"),
+            ("GAME_HEIGHT", "2"),
+            ("GAME_ARENA_WIDTH", "3"),
+        ];
+        let found = env_overrides(
+            &mut config,
+            "GAME_",
+            vars.iter().map(|(k, v)| (k.to_string(), v.to_string())),
+        );
+        assert_eq!(
+            found.overrides,
+            [
+                ("arena.width".to_string(), "3".to_string()),
+                ("width".to_string(), "1".to_string())
+            ]
+        );
+        assert_eq!(found.unknown, ["GAME_HEIGHT"]);
+        assert!(found.ambiguous.is_empty());
+    }
+
+    struct Clash {
+        dotted: i32,
+        underscored: i32,
+    }
+
+    impl IVisitExt for Clash {
+        fn visit_mut_ext(
+            &mut self,
+            f: &mut dyn FnMut(&mut dyn cvar::INode),
+            _: &mut dyn crate::IConsoleExt,
+        ) {
+            f(&mut cvar::Property("arena.width", "", &mut self.dotted, 0));
+            f(&mut cvar::Property(
+                "arena_width",
+                "",
+                &mut self.underscored,
+                0,
+            ));
+        }
+    }
+
+    #[test]
+    fn env_clash() {
+        let mut clash = Clash {
+            dotted: 0,
+            underscored: 0,
+        };
+        let vars = vec![("GAME_ARENA_WIDTH".to_string(), "3".to_string())];
+        let found = env_overrides(&mut clash, "GAME_", vars);
+        assert!(found.overrides.is_empty());
+        assert_eq!(
+            found.ambiguous,
+            [(
+                "GAME_ARENA_WIDTH".to_string(),
+                vec!["arena.width".to_string(), "arena_width".to_string()]
+            )]
+        );
+    }
+}
@@ -0,0 +1,227 @@
+use crate::shell::split;
+use crate::{script_line, Change, ConsoleError, CvarExt, IConsoleExt, IVisitExt, VisitMutExt};
+use std::collections::BTreeMap;
+use std::io;
+use std::path::PathBuf;
+
+/// Property values of a preset, by path
+type Snapshot = BTreeMap<String, String>;
+
+/// Named snapshots of every property, optionally persisted to a directory.
+///
+/// Each preset is stored as `<name>.cfg`, a script of `name value` lines that `exec` can also run.
+#[derive(Default)]
+pub(crate) struct Presets {
+    presets: BTreeMap<String, Snapshot>,
+    dir: Option<PathBuf>,
+}
+
+impl Presets {
+    /// Load every preset in `dir`, and save to it from now on. A missing directory is created
+    /// when the first preset is saved.
+    pub fn set_dir(&mut self, dir: PathBuf) -> io::Result<()> {
+        let entries = match std::fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                self.dir = Some(dir);
+                return Ok(());
+            }
+            Err(e) => return Err(e),
+        };
+        for entry in entries {
+            let path = entry?.path();
+            let name = match path.file_stem() {
+                Some(name) if path.extension().is_some_and(|ext| ext == "cfg") => {
+                    name.to_string_lossy().into_owned()
+                }
+                _ => continue,
+            };
+            let snapshot = parse(&std::fs::read_to_string(&path)?).map_err(|line| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{}:{}: expected `name value`", path.display(), line),
+                )
+            })?;
+            self.presets.insert(name, snapshot);
+        }
+        self.dir = Some(dir);
+        Ok(())
+    }
+
+    fn file(&self, name: &str) -> Option<PathBuf> {
+        self.dir
+            .as_ref()
+            .map(|dir| dir.join(format!("{}.cfg", name)))
+    }
+
+    fn get(&self, name: &str) -> Result<&Snapshot, ConsoleError> {
+        self.presets
+            .get(name)
+            .ok_or_else(|| ConsoleError::InvalidValue(format!("no preset named {}", name)))
+    }
+
+    pub fn cmd_preset(
+        &mut self,
+        args: &[&str],
+        root: &mut dyn IVisitExt,
+        console: &mut dyn IConsoleExt,
+    ) {
+        let out = match args {
+            ["save", name] => self.save(name, root),
+            ["load", name] => self.load(name, root, console),
+            ["list"] => self.list(),
+            ["delete", name] => self.delete(name),
+            ["diff", a, b] => self.diff(a, b),
+            _ => Err(ConsoleError::InvalidUsage(
+                "preset save|load|delete <name>, preset list, preset diff <a> <b>".to_string(),
+            )),
+        };
+        console.write_result(out.into());
+    }
+
+    fn save(&mut self, name: &str, root: &mut dyn IVisitExt) -> Result<String, ConsoleError> {
+        if name.is_empty() || name.contains(['/', '\\', '.']) {
+            return Err(ConsoleError::InvalidValue(format!(
+                "{}: preset names can't contain /, \\ or .",
+                name
+            )));
+        }
+
+        let mut snapshot = Snapshot::new();
+        let mut root = VisitMutExt(|f, console| root.visit_mut_ext(f, console));
+        cvar::console::walk(&mut root, |path, node| {
+            if let cvar::NodeMut::Prop(prop) = node.as_node_mut() {
+                snapshot.insert(path.to_string(), prop.get());
+            }
+        });
+
+        if let Some(file) = self.file(name) {
+            let contents = snapshot
+                .iter()
+                .map(|(path, value)| script_line(path, value))
+                .collect::<String>();
+            let written = file
+                .parent()
+                .map_or(Ok(()), std::fs::create_dir_all)
+                .and_then(|_| std::fs::write(&file, contents));
+            if let Err(e) = written {
+                return Err(ConsoleError::Custom(
+                    format!("Failed to save {}: {}", file.display(), e).into(),
+                ));
+            }
+        }
+
+        let out = format!("Saved preset {} ({} properties)", name, snapshot.len());
+        self.presets.insert(name.to_string(), snapshot);
+        Ok(out)
+    }
+
+    /// Set every property through `CvarExt::set`, reporting each one that fails.
+    ///
+    /// The values that changed are passed to `IConsoleExt::changed`, so they can be undone.
+    fn load(
+        &mut self,
+        name: &str,
+        root: &mut dyn IVisitExt,
+        console: &mut dyn IConsoleExt,
+    ) -> Result<String, ConsoleError> {
+        let snapshot = self.get(name)?;
+        let mut root = VisitMutExt(|f, console| root.visit_mut_ext(f, console));
+        let mut failed = 0;
+        for (path, value) in snapshot {
+            let old = root.get(path).0;
+            let result = root.set(path, value);
+            if result.is_err() {
+                failed += 1;
+                console.write_colored([0.6, 0.6, 0.6, 1.], &format!("{}: ", path));
+                console.write_result(result);
+                continue;
+            }
+            if let (Ok(old), Ok(new)) = (old, root.get(path).0) {
+                if old != new {
+                    let path = path.clone();
+                    console.changed(Change { path, old, new });
+                }
+            }
+        }
+
+        if failed == 0 {
+            Ok(format!("Loaded preset {}", name))
+        } else {
+            Err(ConsoleError::InvalidValue(format!(
+                "{} of {} properties in preset {} could not be set",
+                failed,
+                snapshot.len(),
+                name
+            )))
+        }
+    }
+
+    fn list(&self) -> Result<String, ConsoleError> {
+        if self.presets.is_empty() {
+            return Err(ConsoleError::NoResults);
+        }
+        Ok(self
+            .presets
+            .iter()
+            .map(|(name, snapshot)| format!("{} ({} properties)\n", name, snapshot.len()))
+            .collect())
+    }
+
+    fn delete(&mut self, name: &str) -> Result<String, ConsoleError> {
+        self.get(name)?;
+        if let Some(file) = self.file(name) {
+            match std::fs::remove_file(&file) {
+                Ok(()) => {}
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => {
+                    return Err(ConsoleError::Custom(
+                        format!("Failed to delete {}: {}", file.display(), e).into(),
+                    ))
+                }
+            }
+        }
+        self.presets.remove(name);
+        Ok(format!("Deleted preset {}", name))
+    }
+
+    /// Every property whose value differs between two presets, including ones only in one of them.
+    fn diff(&self, a: &str, b: &str) -> Result<String, ConsoleError> {
+        let (first, second) = (self.get(a)?, self.get(b)?);
+        let mut out = String::new();
+        let missing = "(unset)".to_string();
+        for path in first
+            .keys()
+            .chain(second.keys().filter(|k| !first.contains_key(*k)))
+        {
+            let before = first.get(path).unwrap_or(&missing);
+            let after = second.get(path).unwrap_or(&missing);
+            if before != after {
+                out.push_str(&format!("{}: {} -> {}\n", path, before, after));
+            }
+        }
+
+        if out.is_empty() {
+            out = format!("Presets {} and {} are the same", a, b);
+        }
+        Ok(out)
+    }
+}
+
+/// Read a preset file, or the number of the first line that isn't a `name value` pair.
+fn parse(contents: &str) -> Result<Snapshot, usize> {
+    let mut snapshot = Snapshot::new();
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with("//") || line.starts_with('#') {
+            continue;
+        }
+        match split(line).as_deref() {
+            Ok([path, value]) => {
+                snapshot.insert(path.clone(), value.clone());
+            }
+            _ => return Err(i + 1),
+        }
+    }
+    Ok(snapshot)
+}
@@ -0,0 +1,166 @@
+use imgui::{sys, ImGuiInputTextFlags, ImStr, ImString};
+use std::any::Any;
+use std::os::raw::{c_char, c_int};
+use std::panic::{self, AssertUnwindSafe};
+
+/// Input events forwarded from the prompt's text callback
+pub(crate) enum PromptEvent {
+    Up,
+    Down,
+    Complete,
+    /// Sent every frame while the prompt is active, with `ImGuiInputTextFlags::CallbackAlways`
+    Always,
+}
+
+/// Edit access to the prompt text while imgui owns it
+pub(crate) struct PromptEdit<'a>(&'a mut sys::ImGuiInputTextCallbackData);
+
+impl PromptEdit<'_> {
+    pub fn text(&self) -> &str {
+        // The buffer is unallocated until something is typed, and imgui's own text undo can
+        // briefly leave a negative length
+        if self.0.Buf.is_null() || self.0.BufTextLen <= 0 {
+            return "";
+        }
+        // SAFETY: imgui keeps `BufTextLen` bytes of UTF-8 text at `Buf` for the whole callback,
+        // and `self` borrows the callback data for no longer than that
+        unsafe {
+            let bytes =
+                std::slice::from_raw_parts(self.0.Buf as *const u8, self.0.BufTextLen as usize);
+            std::str::from_utf8_unchecked(bytes)
+        }
+    }
+
+    /// Byte offset of the cursor in `text`.
+    ///
+    /// Kept within `text` and on a character boundary, as imgui doesn't in every case, see `text`.
+    pub fn cursor(&self) -> usize {
+        let text = self.text();
+        let mut cursor = (self.0.CursorPos.max(0) as usize).min(text.len());
+        while !text.is_char_boundary(cursor) {
+            cursor -= 1;
+        }
+        cursor
+    }
+
+    pub fn set_text(&mut self, text: &str) {
+        let len = self.text().len();
+        self.replace(0, len, text);
+    }
+
+    /// Replace the bytes `start..end` with `text`, leaving the cursor after it.
+    pub fn replace(&mut self, start: usize, end: usize, text: &str) {
+        let len = self.text().len();
+        let end = end.min(len);
+        let start = start.min(end);
+        // SAFETY: `data` is the live callback data, and `start..end` lies within its text.
+        // imgui copies the inserted bytes, and drops any that don't fit in the buffer
+        unsafe {
+            let data: *mut sys::ImGuiInputTextCallbackData = self.0;
+            sys::ImGuiInputTextCallbackData_DeleteChars(
+                data,
+                start as c_int,
+                (end - start) as c_int,
+            );
+            let range = text.as_bytes().as_ptr_range();
+            sys::ImGuiInputTextCallbackData_InsertChars(
+                data,
+                start as c_int,
+                range.start as *const c_char,
+                range.end as *const c_char,
+            );
+        }
+        self.set_cursor(start + text.len());
+    }
+
+    /// Move the cursor to byte offset `pos`, dropping any selection.
+    pub fn set_cursor(&mut self, pos: usize) {
+        let pos = pos.min(self.text().len()) as c_int;
+        self.0.CursorPos = pos;
+        self.0.SelectionStart = pos;
+        self.0.SelectionEnd = pos;
+    }
+}
+
+/// The handler passed to `input_prompt`, and what it panicked with, if it did
+struct Callback<'a> {
+    handler: &'a mut dyn FnMut(PromptEvent, &mut PromptEdit),
+    panic: Option<Box<dyn Any + Send>>,
+}
+
+extern "C" fn prompt_callback(data: *mut sys::ImGuiInputTextCallbackData) -> c_int {
+    // SAFETY: imgui passes valid callback data for the duration of the call, with the
+    // `Callback` that `input_prompt` gave it as `UserData`, which outlives `igInputText`
+    let (data, callback) = unsafe {
+        let data = &mut *data;
+        let callback = &mut *(data.UserData as *mut Callback);
+        (data, callback)
+    };
+    // Panics can't unwind through imgui, so stop calling the handler and resume once it returns
+    if callback.panic.is_some() {
+        return 0;
+    }
+    let event = if data.EventFlag == ImGuiInputTextFlags::CallbackHistory.bits() {
+        if data.EventKey == sys::ImGuiKey_UpArrow as i32 {
+            PromptEvent::Up
+        } else {
+            PromptEvent::Down
+        }
+    } else if data.EventFlag == ImGuiInputTextFlags::CallbackCompletion.bits() {
+        PromptEvent::Complete
+    } else if data.EventFlag == ImGuiInputTextFlags::CallbackAlways.bits() {
+        PromptEvent::Always
+    } else {
+        return 0;
+    };
+    let handler = &mut callback.handler;
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        handler(event, &mut PromptEdit(data));
+    }));
+    if let Err(panic) = result {
+        callback.panic = Some(panic);
+    }
+    0
+}
+
+/// Single line text input that lets `handler` rewrite the text while it is being edited.
+///
+/// imgui's `InputText` does not expose the text callbacks we need for history and completion.
+pub(crate) fn input_prompt<F>(
+    _: &imgui::Ui,
+    label: &ImStr,
+    buf: &mut ImString,
+    flags: ImGuiInputTextFlags,
+    mut handler: F,
+) -> bool
+where
+    F: FnMut(PromptEvent, &mut PromptEdit),
+{
+    let mut callback = Callback {
+        handler: &mut handler,
+        panic: None,
+    };
+    // SAFETY: `buf` has room for `capacity_with_nul` bytes and stays nul terminated, and
+    // `callback` lives until `igInputText` returns, which is the last time imgui uses it
+    let (result, text) = unsafe {
+        let result = sys::igInputText(
+            label.as_ptr(),
+            buf.as_mut_ptr(),
+            buf.capacity_with_nul(),
+            flags.bits(),
+            Some(prompt_callback),
+            &mut callback as *mut Callback as *mut _,
+        );
+        // imgui wrote straight into the buffer, resync the length of the ImString
+        let text = std::ffi::CStr::from_ptr(buf.as_ptr())
+            .to_string_lossy()
+            .into_owned();
+        (result, text)
+    };
+    buf.clear();
+    buf.push_str(&text);
+    if let Some(panic) = callback.panic {
+        panic::resume_unwind(panic);
+    }
+    result
+}
@@ -0,0 +1,302 @@
+use crate::ConsoleError;
+
+/// How a command is joined to the one before it on the same line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Chain {
+    /// First command on the line, or separated by `;`. Always runs.
+    Always,
+    /// Separated by `&&`. Only runs if the previous command succeeded.
+    IfOk,
+    /// Separated by `||`. Only runs if the previous command failed.
+    IfErr,
+}
+
+impl Chain {
+    /// Whether a command joined this way should run, given the status of the last command ran.
+    pub fn should_run(self, last_ok: bool) -> bool {
+        match self {
+            Chain::Always => true,
+            Chain::IfOk => last_ok,
+            Chain::IfErr => !last_ok,
+        }
+    }
+}
+
+/// A single command parsed out of a line, see `parse`.
+#[derive(Debug)]
+pub struct Command {
+    pub chain: Chain,
+    pub words: Vec<String>,
+}
+
+#[derive(Debug)]
+enum Token {
+    Word(String),
+    Op(Chain),
+}
+
+/// Split a command line into words, shell style.
+///
+/// Words are separated by any amount of whitespace. Single quotes keep their contents verbatim,
+/// double quotes allow `\"` and `\\` escapes, and outside of quotes a backslash escapes the next
+/// character.
+///
+/// `color "light blue"` becomes `["color", "light blue"]`.
+pub fn split(line: &str) -> Result<Vec<String>, ConsoleError> {
+    Ok(lex(line, false)?
+        .into_iter()
+        .filter_map(|token| match token {
+            Token::Word(word) => Some(word),
+            Token::Op(_) => None,
+        })
+        .collect())
+}
+
+/// Quote a word so that `split` reads it back unchanged.
+///
+/// Words without spaces or special characters are left as is.
+pub fn quote(word: &str) -> String {
+    let plain = !word.is_empty()
+        && !word
+            .chars()
+            .any(|c| c.is_whitespace() || "'\"\\;&|".contains(c));
+    if plain {
+        return word.to_string();
+    }
+    let mut quoted = String::from("\"");
+    for c in word.chars() {
+        if c == '"' || c == '\\' {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Parse a line holding one or more commands.
+///
+/// Commands are separated by `;` (always run), `&&` (run if the previous command succeeded) or
+/// `||` (run if the previous command failed). Words follow the same rules as `split`, so quote or
+/// escape the separators to use them literally.
+///
+/// `width 120; height 80` becomes two commands.
+pub fn parse(line: &str) -> Result<Vec<Command>, ConsoleError> {
+    let mut commands = vec![];
+    let mut chain = Chain::Always;
+    let mut words = vec![];
+
+    for token in lex(line, true)? {
+        match token {
+            Token::Word(word) => words.push(word),
+            Token::Op(next) => {
+                if words.is_empty() {
+                    // Empty commands are harmless between `;`, but `&&`/`||` need something to test
+                    if next != Chain::Always || chain != Chain::Always {
+                        return Err(missing_command());
+                    }
+                } else {
+                    commands.push(Command {
+                        chain,
+                        words: std::mem::take(&mut words),
+                    });
+                }
+                chain = next;
+            }
+        }
+    }
+
+    if !words.is_empty() {
+        commands.push(Command { chain, words });
+    } else if chain != Chain::Always {
+        return Err(missing_command());
+    }
+    Ok(commands)
+}
+
+fn lex(line: &str, ops: bool) -> Result<Vec<Token>, ConsoleError> {
+    let mut tokens = vec![];
+    let mut word = String::new();
+    // Tracked separately from `word.is_empty()` so `""` still produces an (empty) word
+    let mut in_word = false;
+
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                in_word = true;
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => return Err(unterminated('\'')),
+                    }
+                }
+            }
+            '"' => {
+                in_word = true;
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ '"') | Some(c @ '\\') => word.push(c),
+                            Some(c) => {
+                                word.push('\\');
+                                word.push(c);
+                            }
+                            None => return Err(unterminated('"')),
+                        },
+                        Some(c) => word.push(c),
+                        None => return Err(unterminated('"')),
+                    }
+                }
+            }
+            '\\' => {
+                in_word = true;
+                word.push(chars.next().unwrap_or('\\'));
+            }
+            c if c.is_whitespace() => {
+                if in_word {
+                    tokens.push(Token::Word(std::mem::take(&mut word)));
+                    in_word = false;
+                }
+            }
+            ';' | '&' | '|' if ops => {
+                let op = match c {
+                    ';' => Some(Chain::Always),
+                    '&' if chars.peek() == Some(&'&') => Some(Chain::IfOk),
+                    '|' if chars.peek() == Some(&'|') => Some(Chain::IfErr),
+                    _ => None,
+                };
+                if let Some(op) = op {
+                    if op != Chain::Always {
+                        chars.next();
+                    }
+                    if in_word {
+                        tokens.push(Token::Word(std::mem::take(&mut word)));
+                        in_word = false;
+                    }
+                    tokens.push(Token::Op(op));
+                } else {
+                    // A lone `&` or `|` has no special meaning
+                    in_word = true;
+                    word.push(c);
+                }
+            }
+            c => {
+                in_word = true;
+                word.push(c);
+            }
+        }
+    }
+
+    if in_word {
+        tokens.push(Token::Word(word));
+    }
+    Ok(tokens)
+}
+
+fn unterminated(quote: char) -> ConsoleError {
+    ConsoleError::InvalidUsage(format!("missing closing {}", quote))
+}
+
+fn missing_command() -> ConsoleError {
+    ConsoleError::InvalidUsage("expected a command around && or ||".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(command: &Command) -> Vec<&str> {
+        command.words.iter().map(String::as_str).collect()
+    }
+
+    #[test]
+    fn split_words() {
+        assert_eq!(split("  width   120 ").unwrap(), ["width", "120"]);
+        assert_eq!(split("").unwrap(), Vec::<String>::new());
+    }
+
+    #[test]
+    fn split_quotes_and_escapes() {
+        assert_eq!(
+            split(r#"color "light blue""#).unwrap(),
+            ["color", "light blue"]
+        );
+        assert_eq!(split(r#"say 'a "b" \c'"#).unwrap(), ["say", r#"a "b" \c"#]);
+        assert_eq!(
+            split(r#"say "a \"b\" \\ \c""#).unwrap(),
+            ["say", r#"a "b" \ \c"#]
+        );
+        assert_eq!(split(r"light\ blue").unwrap(), ["light blue"]);
+        assert_eq!(split(r#"set name """#).unwrap(), ["set", "name", ""]);
+        assert_eq!(split("a;b&&c").unwrap(), ["a;b&&c"]);
+    }
+
+    #[test]
+    fn split_unterminated() {
+        assert!(split(r#"color "light blue"#).is_err());
+        assert!(split("color 'light").is_err());
+    }
+
+    #[test]
+    fn quote_round_trips() {
+        for word in &[
+            "plain",
+            "",
+            "light blue",
+            r#"say "hi""#,
+            r"back\slash",
+            "it's",
+            "a;b",
+            "a && b",
+            "a|b",
+            "tab\there",
+        ] {
+            assert_eq!(split(&quote(word)).unwrap(), [*word], "{:?}", quote(word));
+        }
+        assert_eq!(quote("plain"), "plain");
+        assert_eq!(quote("light blue"), r#""light blue""#);
+    }
+
+    #[test]
+    fn parse_chains() {
+        let commands = parse("a 1; b && c || d").unwrap();
+        let chains = commands.iter().map(|c| c.chain).collect::<Vec<_>>();
+        assert_eq!(
+            chains,
+            [Chain::Always, Chain::Always, Chain::IfOk, Chain::IfErr]
+        );
+        assert_eq!(words(&commands[0]), ["a", "1"]);
+        assert_eq!(words(&commands[3]), ["d"]);
+    }
+
+    #[test]
+    fn parse_quoted_separators() {
+        let commands = parse(r#"say "a; b" 'c && d' e\|\|f a&b"#).unwrap();
+        assert_eq!(commands.len(), 1);
+        assert_eq!(
+            words(&commands[0]),
+            ["say", "a; b", "c && d", "e||f", "a&b"]
+        );
+    }
+
+    #[test]
+    fn parse_empty_commands() {
+        assert!(parse("").unwrap().is_empty());
+        assert_eq!(parse(";; a ;").unwrap().len(), 1);
+        assert!(parse("&& a").is_err());
+        assert!(parse("a ||").is_err());
+        assert!(parse("a && ; b").is_err());
+    }
+
+    #[test]
+    fn chain_should_run() {
+        assert!(Chain::Always.should_run(false));
+        assert!(Chain::IfOk.should_run(true));
+        assert!(!Chain::IfOk.should_run(false));
+        assert!(Chain::IfErr.should_run(false));
+        assert!(!Chain::IfErr.should_run(true));
+    }
+}
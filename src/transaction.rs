@@ -0,0 +1,28 @@
+/// Property values staged after `begin`, written all at once on `commit`.
+#[derive(Debug, Default)]
+pub struct Transaction {
+    staged: Vec<(String, String)>,
+}
+
+impl Transaction {
+    /// Stage `value` for `path`, replacing anything staged for it before.
+    pub fn stage(&mut self, path: &str, value: &str) {
+        match self.staged.iter_mut().find(|(staged, _)| staged == path) {
+            Some(entry) => entry.1 = value.to_string(),
+            None => self.staged.push((path.to_string(), value.to_string())),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.staged.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.staged.is_empty()
+    }
+
+    /// `(path, value)` pairs in the order they were first staged
+    pub fn into_staged(self) -> Vec<(String, String)> {
+        self.staged
+    }
+}
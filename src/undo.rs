@@ -0,0 +1,107 @@
+use crate::{ConsoleError, CvarExt, IConsoleExt, IVisitExt, VisitMutExt};
+
+/// A property changed from the console, see `VisitMutExt::take_changes`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change {
+    pub path: String,
+    pub old: String,
+    pub new: String,
+}
+
+/// Changes that can be undone and redone, grouped by the command line that made them.
+pub(crate) struct UndoStack {
+    undo: Vec<Vec<Change>>,
+    redo: Vec<Vec<Change>>,
+    limit: usize,
+    /// Changes made by `undo` or `redo` since the last `push`
+    replayed: Vec<Change>,
+}
+
+impl UndoStack {
+    pub fn new(limit: usize) -> Self {
+        UndoStack {
+            undo: vec![],
+            redo: vec![],
+            limit,
+            replayed: vec![],
+        }
+    }
+
+    /// Record the changes made by one command line. Anything undone before can't be redone anymore.
+    pub fn push(&mut self, mut changes: Vec<Change>) {
+        // Undo and redo report what they set like any other command, but aren't steps of their own
+        for replayed in self.replayed.drain(..) {
+            if let Some(i) = changes.iter().position(|change| *change == replayed) {
+                changes.remove(i);
+            }
+        }
+        if changes.is_empty() {
+            return;
+        }
+        self.redo.clear();
+        self.undo.push(changes);
+        if self.undo.len() > self.limit {
+            self.undo.remove(0);
+        }
+    }
+
+    pub fn cmd_undo(&mut self, root: &mut dyn IVisitExt, console: &mut dyn IConsoleExt) {
+        let out = match self.undo.pop() {
+            Some(step) => {
+                let changes = step.iter().rev().map(|c| (&c.path, &c.old));
+                let out = apply(root, changes, &mut self.replayed, console);
+                self.redo.push(step);
+                out
+            }
+            None => Err(ConsoleError::Custom("Nothing to undo".into())),
+        };
+        console.write_result(out.into());
+    }
+
+    pub fn cmd_redo(&mut self, root: &mut dyn IVisitExt, console: &mut dyn IConsoleExt) {
+        let out = match self.redo.pop() {
+            Some(step) => {
+                let changes = step.iter().map(|c| (&c.path, &c.new));
+                let out = apply(root, changes, &mut self.replayed, console);
+                self.undo.push(step);
+                out
+            }
+            None => Err(ConsoleError::Custom("Nothing to redo".into())),
+        };
+        console.write_result(out.into());
+    }
+}
+
+/// Set each `(path, value)`, describing what changed.
+///
+/// Changes are passed to `IConsoleExt::changed` like those of any other action, and kept in
+/// `replayed` so `UndoStack::push` can tell them apart.
+fn apply<'a, I>(
+    root: &mut dyn IVisitExt,
+    changes: I,
+    replayed: &mut Vec<Change>,
+    console: &mut dyn IConsoleExt,
+) -> Result<String, ConsoleError>
+where
+    I: Iterator<Item = (&'a String, &'a String)>,
+{
+    let mut root = VisitMutExt(|f, console| root.visit_mut_ext(f, console));
+    let mut out = String::new();
+    for (path, value) in changes {
+        let old = root.get(path).0;
+        if let Err(e) = &*root.set(path, value) {
+            return Err(ConsoleError::Custom(format!("{}: {}", path, e).into()));
+        }
+        if let (Ok(old), Ok(new)) = (old, root.get(path).0) {
+            out.push_str(&format!("{}: {} -> {}\n", path, old, new));
+            let change = Change {
+                path: path.clone(),
+                old,
+                new,
+            };
+            replayed.push(change.clone());
+            console.changed(change);
+        }
+    }
+    Ok(out)
+}
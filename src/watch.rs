@@ -0,0 +1,123 @@
+use std::collections::HashSet;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// How often to read the file again
+pub(crate) const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Polls in a row the file must be missing for to report it, as editors may remove a file while
+/// saving it
+const MISSING_POLLS: u32 = 2;
+
+/// Polls a config script for changes, see `ConsoleWindow::with_watch`.
+///
+/// Changes are found by comparing contents, as modification times may be too coarse to tell
+/// saves apart.
+pub(crate) struct Watcher {
+    path: PathBuf,
+    interval: Duration,
+    /// Contents of the version of the file that was last run
+    contents: Option<String>,
+    /// Lines of `contents`
+    lines: HashSet<String>,
+    last_poll: Option<Instant>,
+    /// Polls in a row the file could not be read in
+    failures: u32,
+}
+
+impl Watcher {
+    /// Watch `path`, reading it at most once every `interval`.
+    pub fn new(path: PathBuf, interval: Duration) -> Self {
+        Watcher {
+            path,
+            interval,
+            contents: None,
+            lines: HashSet::new(),
+            last_poll: None,
+            failures: 0,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Whether the file was read at least once
+    pub fn loaded(&self) -> bool {
+        self.contents.is_some()
+    }
+
+    /// If the file changed since the last poll, the lines that are new or changed since.
+    ///
+    /// Every other line is blanked out so line numbers still match the file. Failing to read the
+    /// file is only reported once, until it is read again.
+    pub fn poll(&mut self) -> Option<io::Result<String>> {
+        let now = Instant::now();
+        if self
+            .last_poll
+            .is_some_and(|last| now.duration_since(last) < self.interval)
+        {
+            return None;
+        }
+        self.last_poll = Some(now);
+
+        let contents = match std::fs::read_to_string(&self.path) {
+            Ok(contents) => contents,
+            Err(e) => {
+                self.failures += 1;
+                let report = match e.kind() {
+                    io::ErrorKind::NotFound => MISSING_POLLS,
+                    _ => 1,
+                };
+                return if self.failures == report {
+                    Some(Err(e))
+                } else {
+                    None
+                };
+            }
+        };
+        self.failures = 0;
+        if self.contents.as_ref() == Some(&contents) {
+            return None;
+        }
+
+        let mut changed = String::new();
+        for line in contents.lines() {
+            let line = line.trim();
+            if !self.lines.contains(line) {
+                changed.push_str(line);
+            }
+            changed.push('\n');
+        }
+        self.lines = contents
+            .lines()
+            .map(|line| line.trim().to_string())
+            .collect();
+        self.contents = Some(contents);
+        Some(Ok(changed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn changed_lines() {
+        let path = std::env::temp_dir().join(format!("watcher_{}.cfg", std::process::id()));
+        let mut watcher = Watcher::new(path.clone(), Duration::ZERO);
+        assert!(watcher.poll().is_none());
+        assert!(watcher.poll().unwrap().is_err());
+        assert!(watcher.poll().is_none());
+
+        std::fs::write(&path, "a 1\nb 2\n").unwrap();
+        assert_eq!(watcher.poll().unwrap().unwrap(), "a 1\nb 2\n");
+        assert!(watcher.poll().is_none());
+        std::fs::write(&path, "a 1\n  b 3\nc 4\n").unwrap();
+        assert_eq!(watcher.poll().unwrap().unwrap(), "\nb 3\nc 4\n");
+        std::fs::remove_file(&path).unwrap();
+        assert!(watcher.poll().is_none());
+        assert!(watcher.loaded());
+    }
+}